@@ -0,0 +1,83 @@
+/* global L */
+
+const map = L.map('map', { zoomSnap: 0.25 }).fitBounds([
+  [26.169463, -124.725839],
+  [50.884358, -66.949895],
+]);
+
+L.tileLayer('https://services.arcgisonline.com/ArcGIS/rest/services/World_Imagery/MapServer/tile/{z}/{y}/{x}', {
+  maxZoom: 18,
+  attribution: '<a href="https://www.sbnation.com/secret-base/21410129/20020"><i>20020</i></a> '
+    + 'by Secret Base. © 2020 Vox Media, Inc. All Rights Reserved. '
+    + 'Imagery: Esri, Maxar, Earthstar Geographics',
+}).addTo(map);
+
+// Team name -> the line and stadium layers for that team.
+const teams = new Map();
+let selected = null;
+
+function style(feature, highlighted) {
+  return {
+    color: feature.properties.color,
+    weight: highlighted ? 6 : 3,
+    opacity: selected === null || highlighted ? 1 : 0.5,
+  };
+}
+
+function showTeam() {
+  const match = /^#team=(.*)$/.exec(window.location.hash);
+  selected = match ? decodeURIComponent(match[1]) : null;
+  teams.forEach((layers, team) => {
+    layers.forEach((layer) => {
+      if (layer.setStyle && layer.feature.properties.kind === 'line') {
+        layer.setStyle(style(layer.feature, team === selected));
+      }
+    });
+  });
+  const layers = teams.get(selected);
+  if (layers) {
+    map.fitBounds(L.featureGroup(layers).getBounds(), { padding: [40, 40] });
+  }
+}
+
+function selectTeam(team) {
+  window.location.hash = `team=${encodeURIComponent(team)}`;
+}
+
+fetch(document.getElementById('map').dataset.geojson)
+  .then((response) => response.json())
+  .then((geojson) => {
+    L.geoJSON(geojson, {
+      style: (feature) => style(feature, false),
+      pointToLayer: (feature, latlng) => L.circleMarker(latlng, {
+        radius: 5,
+        color: 'white',
+        weight: 1,
+        fillColor: feature.properties.color,
+        fillOpacity: 1,
+      }),
+      onEachFeature: (feature, layer) => {
+        const { team } = feature.properties;
+        if (!teams.has(team)) {
+          teams.set(team, []);
+        }
+        teams.get(team).push(layer);
+        layer.bindTooltip(team, { sticky: true });
+        layer.on('click', () => selectTeam(team));
+      },
+    }).addTo(map);
+    showTeam();
+  });
+
+window.addEventListener('hashchange', showTeam);
+
+document.getElementById('search').addEventListener('submit', (event) => {
+  event.preventDefault();
+  const query = event.target.elements[0].value.trim().toLowerCase();
+  const options = Array.from(document.getElementById('teams').options);
+  const option = options.find((o) => o.value.toLowerCase() === query || o.text.toLowerCase() === query)
+    || options.find((o) => o.value.toLowerCase().startsWith(query));
+  if (query && option) {
+    selectTeam(option.value);
+  }
+});
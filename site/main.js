@@ -8,6 +8,9 @@ Cesium.Camera.DEFAULT_VIEW_FACTOR = 0.01;
 
 const viewer = new Cesium.Viewer('map', {
   animation: false,
+  // The KML's chapter TimeSpans are for Google Earth's time slider; without a timeline here, stay
+  // at the present (after every chapter) so that everything is shown.
+  automaticallyTrackDataSourceClocks: false,
   baseLayerPicker: false,
   geocoder: false,
   imageryProvider: new Cesium.ArcGisMapServerImageryProvider({
@@ -19,7 +22,7 @@ const viewer = new Cesium.Viewer('map', {
   timeline: false,
 });
 
-viewer.dataSources.add(Cesium.KmlDataSource.load('20020.kml', {
+viewer.dataSources.add(Cesium.KmlDataSource.load(document.getElementById('map').dataset.kml, {
   camera: viewer.camera,
   canvas: viewer.canvas,
   credit: new Cesium.Credit(
@@ -0,0 +1,215 @@
+use crate::geo::*;
+use crate::survey::{self, Method, Survey};
+use crate::template::{Compare, ComparedFile};
+use anyhow::Result;
+use askama::Template;
+use std::panic;
+use uom::si::f64::Length;
+use uom::si::length::{meter, yard};
+
+const CENTERFIT: &str = "[[navarro::centerfit]]";
+
+/// How far from the consensus the default method can be before a special case is worth it.
+const TOLERANCE_METERS: f64 = 1.0;
+const TOLERANCE_DEGREES: f64 = 0.1;
+
+/// One way of surveying a file.
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    pub method: Method,
+    pub centerfit: bool,
+    pub survey: Survey,
+    /// How far the field is from the consensus.
+    pub distance: Length,
+    /// How far the heading is from the consensus, in degrees (as an axis, so at most 90°).
+    pub turn: f64,
+}
+
+impl Estimate {
+    pub fn name(&self) -> String {
+        if self.centerfit {
+            format!("{} (centerfit)", self.method)
+        } else {
+            self.method.to_string()
+        }
+    }
+
+    /// The field's axis from goal line to goal line, for drawing.
+    pub fn axis(&self) -> Line {
+        let field = Point::from(self.survey.field);
+        let half = Length::new::<yard>(50.0).get::<meter>();
+        Line {
+            start: field
+                .haversine_destination(self.survey.bearing + 180.0, half)
+                .into(),
+            end: field
+                .haversine_destination(self.survey.bearing, half)
+                .into(),
+        }
+    }
+
+    fn score(&self) -> f64 {
+        self.distance.get::<meter>() / TOLERANCE_METERS + self.turn / TOLERANCE_DEGREES
+    }
+}
+
+/// Every method (and `centerfit` variant) that fits a survey file, side by side.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Only estimates that differ from each other; a `centerfit` variant that changes nothing is
+    /// left out.
+    pub estimates: Vec<Estimate>,
+    /// The median location and heading of the estimates.
+    pub consensus: Survey,
+    /// Which estimate `survey::default` (what the map uses) picks, if any.
+    pub default: Option<usize>,
+}
+
+impl Comparison {
+    /// `None` if no method fits the file.
+    pub fn new(kml: &str) -> Option<Comparison> {
+        let plain = kml.replace(CENTERFIT, "");
+        let centerfit = format!("{}<!-- {} -->", plain, CENTERFIT);
+
+        let mut estimates: Vec<Estimate> = Vec::new();
+        for &method in &Method::ALL {
+            if !method.fits(kml) {
+                continue;
+            }
+            for (kml, centerfit) in &[(&plain, false), (&centerfit, true)] {
+                let survey = match panic::catch_unwind(|| method.survey(kml)) {
+                    Ok(survey) if survey.field.x.is_finite() && survey.bearing.is_finite() => {
+                        survey
+                    }
+                    _ => continue,
+                };
+                if estimates
+                    .iter()
+                    .any(|estimate| same(estimate.survey, survey))
+                {
+                    continue;
+                }
+                estimates.push(Estimate {
+                    method,
+                    centerfit: *centerfit,
+                    survey,
+                    distance: Length::new::<meter>(0.0),
+                    turn: 0.0,
+                });
+            }
+        }
+
+        let reference = estimates.first()?.survey.bearing;
+        let consensus = Survey {
+            field: Coordinate {
+                x: median(estimates.iter().map(|estimate| estimate.survey.field.x)),
+                y: median(estimates.iter().map(|estimate| estimate.survey.field.y)),
+            },
+            bearing: reference
+                + median(
+                    estimates
+                        .iter()
+                        .map(|estimate| axial(estimate.survey.bearing - reference)),
+                ),
+        };
+        for estimate in &mut estimates {
+            estimate.distance = Length::new::<meter>(
+                Point::from(estimate.survey.field)
+                    .haversine_distance(&Point::from(consensus.field)),
+            );
+            estimate.turn = axial(estimate.survey.bearing - consensus.bearing).abs();
+        }
+
+        let default = panic::catch_unwind(|| survey::default(kml))
+            .ok()
+            .and_then(|default| {
+                estimates
+                    .iter()
+                    .position(|estimate| same(estimate.survey, default))
+            });
+        Some(Comparison {
+            estimates,
+            consensus,
+            default,
+        })
+    }
+
+    /// The farthest apart any two estimates' fields are.
+    pub fn location_spread(&self) -> Length {
+        let mut spread = 0.0_f64;
+        for (i, a) in self.estimates.iter().enumerate() {
+            for b in &self.estimates[i + 1..] {
+                spread = spread.max(
+                    Point::from(a.survey.field).haversine_distance(&Point::from(b.survey.field)),
+                );
+            }
+        }
+        Length::new::<meter>(spread)
+    }
+
+    /// The farthest apart any two estimates' headings are, in degrees.
+    pub fn heading_spread(&self) -> f64 {
+        let mut spread = 0.0_f64;
+        for (i, a) in self.estimates.iter().enumerate() {
+            for b in &self.estimates[i + 1..] {
+                spread = spread.max(axial(a.survey.bearing - b.survey.bearing).abs());
+            }
+        }
+        spread
+    }
+
+    /// What `survey::default` picks if it's within a meter and a tenth of a degree of the
+    /// consensus, so that special cases are only recommended where they make a difference.
+    /// Otherwise, the estimate closest to the consensus, counting a tenth of a degree as much as a
+    /// meter (at the length of a line across the country, the heading matters far more), unless
+    /// the default is within that much of it (as it always is between two estimates).
+    pub fn recommended(&self) -> Option<usize> {
+        let best = self
+            .estimates
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.score().partial_cmp(&b.score()).unwrap())
+            .map(|(i, _)| i)?;
+        match self.default {
+            Some(i)
+                if self.estimates[i].distance.get::<meter>() <= TOLERANCE_METERS
+                    && self.estimates[i].turn <= TOLERANCE_DEGREES =>
+            {
+                Some(i)
+            }
+            Some(i) if self.estimates[i].score() <= self.estimates[best].score() + 1.0 => Some(i),
+            _ => Some(best),
+        }
+    }
+}
+
+/// A KML document with every estimate of each named survey, colored by method.
+pub fn kml(comparisons: &[(String, Comparison)]) -> Result<String> {
+    Ok(Compare {
+        files: comparisons
+            .iter()
+            .map(|(name, comparison)| ComparedFile { name, comparison })
+            .collect(),
+    }
+    .render()?)
+}
+
+fn same(a: Survey, b: Survey) -> bool {
+    a.field == b.field && a.bearing == b.bearing
+}
+
+/// An angle between two headings as axes, from -90° to 90°.
+fn axial(degrees: f64) -> f64 {
+    (degrees + 90.0).rem_euclid(180.0) - 90.0
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    }
+}
@@ -0,0 +1,90 @@
+use crate::geo::*;
+use crate::survey::{self, Survey};
+use crate::template::{Diff, DiffVersion};
+use crate::Boundary;
+use anyhow::{anyhow, Result};
+use askama::Template;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use uom::si::f64::Length;
+use uom::si::length::meter;
+
+/// How a survey changed between two versions of its KML.
+#[derive(Debug, Clone, Copy)]
+pub struct SurveyDiff {
+    pub old: Survey,
+    pub new: Survey,
+    /// How far the field moved.
+    pub shift: Length,
+    /// Change in the field's bearing, in degrees clockwise (-180 to 180).
+    pub rotation: f64,
+    /// The lines clipped to the boundary, if they reach it.
+    pub old_line: Option<Line>,
+    pub new_line: Option<Line>,
+}
+
+impl SurveyDiff {
+    pub fn new(old: &str, new: &str, boundary: &Boundary) -> SurveyDiff {
+        let (old, new) = (survey::default(old), survey::default(new));
+        SurveyDiff {
+            old,
+            new,
+            shift: distance(old.field, new.field),
+            rotation: (new.bearing - old.bearing + 180.0).rem_euclid(360.0) - 180.0,
+            old_line: boundary.limit(&old),
+            new_line: boundary.limit(&new),
+        }
+    }
+
+    /// How far the west and east ends of the clipped line moved.
+    pub fn endpoint_shifts(&self) -> Option<(Length, Length)> {
+        let (old, new) = (self.old_line?, self.new_line?);
+        Some((distance(old.start, new.start), distance(old.end, new.end)))
+    }
+
+    /// A KML document with both versions of survey `name`, the old one (labelled `old`) in red
+    /// and the new one in green.
+    pub fn kml(&self, name: &str, old: &str, new: &str) -> Result<String> {
+        let line =
+            |line: Option<Line>| line.map_or_else(Vec::new, |line| line.interpolate().collect());
+        Ok(Diff {
+            name,
+            versions: [
+                DiffVersion {
+                    label: old,
+                    color: [0xff, 0x00, 0x00],
+                    field: self.old.field,
+                    line: line(self.old_line),
+                },
+                DiffVersion {
+                    label: new,
+                    color: [0x00, 0xff, 0x00],
+                    field: self.new.field,
+                    line: line(self.new_line),
+                },
+            ],
+        }
+        .render()?)
+    }
+}
+
+fn distance(a: Coordinate, b: Coordinate) -> Length {
+    Length::new::<meter>(Point::from(a).haversine_distance(&Point::from(b)))
+}
+
+/// Reads `spec` as a file if there is one, otherwise as `revision:path` from git.
+pub fn read(spec: &str) -> Result<String> {
+    if Path::new(spec).is_file() {
+        return Ok(fs::read_to_string(spec)?);
+    }
+    let output = Command::new("git").args(&["show", spec]).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{:?} isn't a file or a git object: {}",
+            spec,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
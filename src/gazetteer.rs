@@ -0,0 +1,342 @@
+use crate::geo::*;
+use crate::ord::OrdF64;
+use crate::output::{quote, write_atomic, OutputWriter, Site};
+use crate::query::{cross_track, Side};
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use uom::si::f64::Length;
+use uom::si::length::{meter, mile};
+
+lazy_static! {
+    /// How far from the line a city can be and still be listed.
+    static ref BUFFER: Length = Length::new::<mile>(15.0);
+    /// Without `states.kml`, stretches shorter than this are taken to be the nearest place being
+    /// across a state line, and are folded into the stretch before them.
+    static ref MIN_STRETCH: Length = Length::new::<mile>(25.0);
+}
+
+/// Where a line goes, according to `data/places.csv` and `data/states.kml`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Gazetteer {
+    /// Each stretch of the line within a state, west to east. Without `states.kml`, each point on
+    /// the line is counted as being in the state of the nearest place, and short stretches are
+    /// folded into their neighbors, so this is only as good as `places.csv` is dense.
+    pub states: Vec<Stretch>,
+    /// Places within `BUFFER` of the line, west to east.
+    pub cities: Vec<City>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stretch {
+    pub state: String,
+    pub length: Length,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct City {
+    pub place: Place,
+    pub distance: Length,
+    pub side: Side,
+}
+
+impl Gazetteer {
+    pub fn new(line: &[Coordinate], places: &[Place], states: &[StateBoundary]) -> Gazetteer {
+        let state_at = |point: Coordinate| {
+            if states.is_empty() {
+                Place::nearest(places, point).map(|(place, _)| place.state.as_str())
+            } else {
+                states
+                    .iter()
+                    .find(|state| state.contains(point))
+                    .map(|state| state.name.as_str())
+            }
+        };
+        let mut stretches: Vec<Stretch> = Vec::new();
+        for (&start, &end) in line.iter().tuple_windows() {
+            let state = match state_at((start + end) / 2.0) {
+                Some(state) => state,
+                None => continue,
+            };
+            let length = Length::new::<meter>(Line { start, end }.haversine_length());
+            match stretches.last_mut() {
+                Some(stretch) if stretch.state == state => stretch.length += length,
+                _ => stretches.push(Stretch {
+                    state: state.to_string(),
+                    length,
+                }),
+            }
+        }
+        if states.is_empty() {
+            let mut smoothed: Vec<Stretch> = Vec::new();
+            for stretch in stretches {
+                match smoothed.last_mut() {
+                    Some(last) if last.state == stretch.state || stretch.length < *MIN_STRETCH => {
+                        last.length += stretch.length
+                    }
+                    _ => smoothed.push(stretch),
+                }
+            }
+            stretches = smoothed;
+        }
+
+        let degrees = BUFFER.get::<meter>() / 111_000.0;
+        let (west, east) = line
+            .iter()
+            .map(|c| c.x)
+            .minmax()
+            .into_option()
+            .unwrap_or_default();
+        let (south, north) = line
+            .iter()
+            .map(|c| c.y)
+            .minmax()
+            .into_option()
+            .unwrap_or_default();
+        let mut cities = places
+            .iter()
+            .filter(|place| {
+                let scale = place.location.y.to_radians().cos();
+                place.location.x >= west - degrees / scale
+                    && place.location.x <= east + degrees / scale
+                    && place.location.y >= south - degrees
+                    && place.location.y <= north + degrees
+            })
+            .filter_map(|place| {
+                let (i, (distance, side)) = line
+                    .iter()
+                    .tuple_windows()
+                    .map(|(&start, &end)| cross_track(Line { start, end }, place.location))
+                    .enumerate()
+                    .min_by(|(_, (a, _)), (_, (b, _))| a.partial_cmp(b).unwrap())?;
+                let distance = Length::new::<meter>(distance);
+                if distance <= *BUFFER {
+                    Some((
+                        i,
+                        City {
+                            place: place.clone(),
+                            distance,
+                            side,
+                        },
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        cities.sort_by_key(|(i, _)| *i);
+
+        Gazetteer {
+            states: stretches,
+            cities: cities.into_iter().map(|(_, city)| city).collect(),
+        }
+    }
+
+    /// Total length of the line in each state, in the order they're first reached.
+    pub fn lengths(&self) -> Vec<(&str, Length)> {
+        let mut lengths: Vec<(&str, Length)> = Vec::new();
+        for stretch in &self.states {
+            match lengths
+                .iter_mut()
+                .find(|(state, _)| *state == stretch.state)
+            {
+                Some((_, length)) => *length += stretch.length,
+                None => lengths.push((&stretch.state, stretch.length)),
+            }
+        }
+        lengths
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub name: String,
+    pub state: String,
+    pub location: Coordinate,
+}
+
+impl Place {
+    /// The place closest to `point`, and how far away it is.
+    pub fn nearest(places: &[Place], point: Coordinate) -> Option<(&Place, Length)> {
+        places
+            .iter()
+            .map(|place| {
+                let distance = Point::from(place.location).haversine_distance(&Point::from(point));
+                (place, OrdF64(distance))
+            })
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(place, distance)| (place, Length::new::<meter>(distance.0)))
+    }
+}
+
+/// Parses a row of `data/places.csv`.
+impl FromStr for Place {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Place> {
+        let mut iter = s.split(',');
+        let mut next = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("not enough columns: {:?}", s))
+        };
+        let name = next()?.to_string();
+        let state = next()?.to_string();
+        let (lat, lon) = (next()?.parse()?, next()?.parse()?);
+        Ok(Place {
+            name,
+            state,
+            location: Coordinate { x: lon, y: lat },
+        })
+    }
+}
+
+/// A state's outline, from the optional `data/states.kml`.
+#[derive(Debug, Clone)]
+pub struct StateBoundary {
+    pub name: String,
+    /// Every ring of every polygon, inner or outer.
+    pub rings: Vec<LineString>,
+    bounds: Vec<Rect>,
+}
+
+impl StateBoundary {
+    /// Reads each `<Placemark>` as a state, such as in the Census Bureau's cartographic boundary
+    /// files. The `STUSPS` field (the postal abbreviation) is used as the name if there is one.
+    pub fn load(kml: &str) -> Vec<StateBoundary> {
+        lazy_static! {
+            static ref PLACEMARK: Regex = Regex::new(r"(?s)<Placemark.*?</Placemark>").unwrap();
+            static ref STUSPS: Regex =
+                Regex::new(r#"<SimpleData name="STUSPS">([^<]*)</SimpleData>"#).unwrap();
+            static ref NAME: Regex = Regex::new(r"<name>([^<]*)</name>").unwrap();
+            static ref COORDINATES: Regex =
+                Regex::new(r"(?s)<coordinates>(.*?)</coordinates>").unwrap();
+        }
+
+        PLACEMARK
+            .find_iter(kml)
+            .filter_map(|placemark| {
+                let placemark = placemark.as_str();
+                let name = STUSPS
+                    .captures(placemark)
+                    .or_else(|| NAME.captures(placemark))?[1]
+                    .trim()
+                    .to_string();
+                let rings = COORDINATES
+                    .captures_iter(placemark)
+                    .map(|captures| {
+                        captures[1]
+                            .split_whitespace()
+                            .filter_map(|point| {
+                                point
+                                    .splitn(3, ',')
+                                    .take(2)
+                                    .filter_map(|f| f.parse().ok())
+                                    .collect_tuple::<(f64, f64)>()
+                            })
+                            .collect::<LineString>()
+                    })
+                    .collect::<Vec<_>>();
+                let bounds = rings
+                    .iter()
+                    .map(|ring| ring.bounding_rect())
+                    .collect::<Option<Vec<_>>>()?;
+                Some(StateBoundary {
+                    name,
+                    rings,
+                    bounds,
+                })
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, point: Coordinate) -> bool {
+        let crossings = self
+            .rings
+            .iter()
+            .zip(&self.bounds)
+            .filter(|(_, bounds)| {
+                (bounds.min().x..=bounds.max().x).contains(&point.x)
+                    && (bounds.min().y..=bounds.max().y).contains(&point.y)
+            })
+            .flat_map(|(ring, _)| ring.lines())
+            .filter(|line| (line.start.y <= point.y) != (line.end.y <= point.y))
+            .filter(|line| {
+                let t = (point.y - line.start.y) / (line.end.y - line.start.y);
+                line.start.x + (line.end.x - line.start.x) * t < point.x
+            })
+            .count();
+        crossings % 2 == 1
+    }
+}
+
+/// `gazetteer.csv`: the states and cities along each team's line.
+pub struct Gazetteers;
+
+impl OutputWriter for Gazetteers {
+    fn name(&self) -> &'static str {
+        "gazetteer"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        write_atomic(&dir.join("gazetteer.csv"), |file| {
+            writeln!(file, "team,abbr,states,cities")?;
+            for field in site.fields {
+                writeln!(
+                    file,
+                    "{},{},{},{}",
+                    quote(&field.team.name),
+                    quote(&field.team.abbr),
+                    quote(
+                        &field
+                            .gazetteer
+                            .states
+                            .iter()
+                            .map(|stretch| &stretch.state)
+                            .join(";")
+                    ),
+                    quote(
+                        &field
+                            .gazetteer
+                            .cities
+                            .iter()
+                            .map(|city| format!("{} {}", city.place.name, city.place.state))
+                            .join(";")
+                    ),
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// `states.csv`: how many miles of each team's line are in each state.
+pub struct StateLengths;
+
+impl OutputWriter for StateLengths {
+    fn name(&self) -> &'static str {
+        "states"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        write_atomic(&dir.join("states.csv"), |file| {
+            writeln!(file, "team,abbr,state,miles")?;
+            for field in site.fields {
+                for (state, length) in field.gazetteer.lengths() {
+                    writeln!(
+                        file,
+                        "{},{},{},{:.1}",
+                        quote(&field.team.name),
+                        quote(&field.team.abbr),
+                        quote(state),
+                        length.get::<mile>(),
+                    )?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -1,11 +1,16 @@
 pub use geo::prelude::*;
 
 use geo::algorithm::line_interpolate_point::LineInterpolatePoint;
+use geo::algorithm::simplify::SimplifyIdx;
 
 pub type Coordinate = geo::Coordinate<f64>;
 pub type Line = geo::Line<f64>;
 pub type LineString = geo::LineString<f64>;
 pub type Point = geo::Point<f64>;
+pub type Rect = geo::Rect<f64>;
+
+/// The mean radius of the earth, in meters, as used by `geo`'s haversine algorithms.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
 
 pub trait CoordinateExt {
     fn bearing_from_slope(self, slope: f64) -> f64;
@@ -75,6 +80,12 @@ pub struct Interpolate {
     done: bool,
 }
 
+impl Interpolate {
+    pub fn line(&self) -> Line {
+        self.line
+    }
+}
+
 impl Iterator for Interpolate {
     type Item = Coordinate;
 
@@ -89,3 +100,37 @@ impl Iterator for Interpolate {
         }
     }
 }
+
+/// Simplifies a line as it's drawn with KML's `<tessellate>`, where each segment follows a great
+/// circle, dropping points that are within `epsilon` meters of the segment drawn in their place.
+///
+/// Great circles are straight lines in a gnomonic projection, so this is plain Ramer–Douglas–Peucker
+/// after projecting around the line's midpoint (which is accurate enough for lines a couple
+/// thousand kilometers long).
+pub fn simplify_tessellated(line: &[Coordinate], epsilon: f64) -> Vec<Coordinate> {
+    let (first, last) = match (line.first(), line.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec::new(),
+    };
+    let (lon_0, lat_0) = (
+        ((first.x + last.x) / 2.0).to_radians(),
+        ((first.y + last.y) / 2.0).to_radians(),
+    );
+    let projected = line
+        .iter()
+        .map(|c| {
+            let (lon, lat) = (c.x.to_radians(), c.y.to_radians());
+            let cos_c = lat_0.sin() * lat.sin() + lat_0.cos() * lat.cos() * (lon - lon_0).cos();
+            Coordinate {
+                x: lat.cos() * (lon - lon_0).sin() / cos_c,
+                y: (lat_0.cos() * lat.sin() - lat_0.sin() * lat.cos() * (lon - lon_0).cos())
+                    / cos_c,
+            }
+        })
+        .collect::<LineString>();
+    projected
+        .simplify_idx(&(epsilon / EARTH_RADIUS))
+        .into_iter()
+        .map(|i| line[i])
+        .collect()
+}
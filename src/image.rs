@@ -1,26 +1,32 @@
 use crate::template::Label;
+use crate::timing;
 use crate::Team;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use askama::Template;
 use contrast::contrast;
 use tiny_skia::{Color, Pixmap};
 
-fn encode(pixmap: &Pixmap) -> Result<Vec<u8>> {
-    Ok(oxipng::optimize_from_memory(
-        &pixmap.encode_png()?,
-        &Default::default(),
-    )?)
+/// Images are encoded in parallel with each other, so oxipng's own `parallel` feature is off: it
+/// hands filters to rayon and then blocks on a channel, which deadlocks once every rayon worker is
+/// waiting inside `encode`.
+pub fn encode(pixmap: &Pixmap) -> Result<Vec<u8>> {
+    timing::time("png", || {
+        Ok(oxipng::optimize_from_memory(
+            &pixmap.encode_png()?,
+            &Default::default(),
+        )?)
+    })
 }
 
-pub(crate) fn field(team: &Team) -> Result<Vec<u8>> {
+pub fn field(team: &Team) -> Pixmap {
     let mut color = Color::from_rgba8(team.color[0], team.color[1], team.color[2], u8::MAX);
     color.set_alpha(0.7);
     let mut pixmap = Pixmap::new(1, 20).unwrap();
     pixmap.fill(color);
-    encode(&pixmap)
+    pixmap
 }
 
-pub(crate) fn label(team: &Team) -> Result<Vec<u8>> {
+pub fn label(team: &Team) -> Result<Pixmap> {
     let black: f64 = contrast(team.color.into(), [0, 0, 0].into());
     let white: f64 = contrast(team.color.into(), [255, 255, 255].into());
     let svg = Label {
@@ -29,6 +35,11 @@ pub(crate) fn label(team: &Team) -> Result<Vec<u8>> {
     }
     .render()?;
 
+    timing::time("label", || render(&svg, 160, 360, 1.0))
+}
+
+/// Renders an SVG of size `width`×`height`, scaled up by `zoom`, with Roboto Bold available.
+pub fn render(svg: &str, width: u32, height: u32, zoom: f32) -> Result<Pixmap> {
     lazy_static::lazy_static! {
         static ref OPTIONS: usvg::Options = usvg::Options {
             fontdb: {
@@ -40,8 +51,12 @@ pub(crate) fn label(team: &Team) -> Result<Vec<u8>> {
         };
     }
 
-    let tree = usvg::Tree::from_str(&svg, &OPTIONS)?;
-    let mut pixmap = Pixmap::new(160, 360).unwrap();
-    resvg::render(&tree, usvg::FitTo::Original, pixmap.as_mut());
-    encode(&pixmap)
+    let tree = usvg::Tree::from_str(svg, &OPTIONS)?;
+    let mut pixmap = Pixmap::new(
+        (width as f32 * zoom).ceil() as u32,
+        (height as f32 * zoom).ceil() as u32,
+    )
+    .ok_or_else(|| anyhow!("can't render a {}x{} image at {}x", width, height, zoom))?;
+    resvg::render(&tree, usvg::FitTo::Zoom(zoom), pixmap.as_mut());
+    Ok(pixmap)
 }
@@ -0,0 +1,105 @@
+use crate::gazetteer::Place;
+use crate::geo::*;
+use crate::output::{quote, write_atomic, OutputWriter, Site};
+use crate::template::{Field, Intersections as IntersectionsTemplate};
+use anyhow::Result;
+use askama::Template;
+use std::io::Write;
+use std::path::Path;
+use uom::si::f64::Length;
+use uom::si::length::{meter, mile};
+
+/// Where two teams' lines cross.
+#[derive(Debug, Clone)]
+pub struct Intersection<'a> {
+    pub a: &'a Field,
+    pub b: &'a Field,
+    pub location: Coordinate,
+    /// Distance from `a`'s stadium.
+    pub a_distance: Length,
+    /// Distance from `b`'s stadium.
+    pub b_distance: Length,
+    pub nearest: Option<(&'a Place, Length)>,
+}
+
+/// Every crossing between two lines, in the order the teams are listed.
+pub fn intersections<'a>(fields: &[&'a Field], places: &'a [Place]) -> Vec<Intersection<'a>> {
+    let distance = |a: Coordinate, b: Coordinate| {
+        Length::new::<meter>(Point::from(a).haversine_distance(&Point::from(b)))
+    };
+
+    let mut intersections = Vec::new();
+    for (i, a) in fields.iter().enumerate() {
+        for b in &fields[i + 1..] {
+            let (line_a, line_b) = (a.line.line(), b.line.line());
+            let location = match line_a.intersection(line_b) {
+                Some(c) if line_a.roughly_contains(c) && line_b.roughly_contains(c) => c,
+                _ => continue,
+            };
+            intersections.push(Intersection {
+                a,
+                b,
+                location,
+                a_distance: distance(location, a.stadium),
+                b_distance: distance(location, b.stadium),
+                nearest: Place::nearest(places, location),
+            });
+        }
+    }
+    intersections
+}
+
+/// `intersections.kml` and `intersections.csv`: where every pair of lines cross, how far that is
+/// from each stadium, and the nearest city.
+pub struct Intersections;
+
+impl OutputWriter for Intersections {
+    fn name(&self) -> &'static str {
+        "intersections"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let intersections = intersections(site.fields, site.places);
+
+        let kml = IntersectionsTemplate {
+            revision: site.revision,
+            intersections: &intersections,
+        }
+        .render()?;
+        write_atomic(&dir.join("intersections.kml"), |file| {
+            Ok(file.write_all(kml.as_bytes())?)
+        })?;
+
+        write_atomic(&dir.join("intersections.csv"), |file| {
+            writeln!(
+                file,
+                "team_a,team_b,latitude,longitude,miles_from_a,miles_from_b,\
+                 nearest_city,nearest_state,miles_from_city"
+            )?;
+            for x in &intersections {
+                let (city, state, city_distance) = match x.nearest {
+                    Some((place, distance)) => (
+                        place.name.as_str(),
+                        place.state.as_str(),
+                        format!("{:.1}", distance.get::<mile>()),
+                    ),
+                    None => ("", "", String::new()),
+                };
+                writeln!(
+                    file,
+                    "{},{},{:.5},{:.5},{:.1},{:.1},{},{},{}",
+                    quote(&x.a.team.name),
+                    quote(&x.b.team.name),
+                    x.location.y,
+                    x.location.x,
+                    x.a_distance.get::<mile>(),
+                    x.b_distance.get::<mile>(),
+                    quote(city),
+                    quote(state),
+                    city_distance,
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,168 @@
+//! Computes the Bowl Game map from _20020_: fields from stadium surveys, their lines clipped to the
+//! US boundary, and the KML/KMZ documents and images that draw them.
+
+#![deny(rust_2018_idioms)]
+#![allow(clippy::map_entry)] // https://github.com/rust-lang/rust-clippy/issues/1450
+
+pub mod compare;
+pub mod diff;
+pub mod gazetteer;
+pub mod geo;
+mod image;
+mod intersections;
+mod mvt;
+mod ord;
+pub mod output;
+mod pmtiles;
+mod poster;
+pub mod query;
+#[cfg(feature = "hotwatch")]
+mod serve;
+mod state;
+pub mod story;
+pub mod survey;
+#[cfg(feature = "synthetic")]
+pub mod synthetic;
+mod template;
+mod territory;
+mod tiles;
+mod timing;
+
+#[cfg(feature = "hotwatch")]
+pub use crate::serve::Server;
+pub use crate::state::{field, Change, Data, State};
+pub use crate::survey::{Method, Survey};
+pub use crate::template::{Field, LineLod};
+pub use crate::timing::report as report_timings;
+
+use crate::geo::*;
+use crate::ord::OrdF64;
+use anyhow::{anyhow, Result};
+use hex::FromHex;
+use itertools::Itertools;
+use std::io::prelude::*;
+use std::str::FromStr;
+use uom::si::f64::Length;
+use uom::si::length::meter;
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Team {
+    pub name: String,
+    pub abbr: String,
+    pub color: [u8; 3],
+}
+
+/// Parses a row of `data/teams.csv`.
+impl FromStr for Team {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Team> {
+        let mut iter = s.split(',');
+        let mut next = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("not enough columns: {:?}", s))
+        };
+        Ok(Team {
+            name: next()?.to_string(),
+            abbr: next()?.to_string(),
+            color: <[u8; 3]>::from_hex(next()?.trim_start_matches('#'))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LatLonBox {
+    pub north: f64,
+    pub south: f64,
+    pub east: f64,
+    pub west: f64,
+}
+
+impl LatLonBox {
+    pub fn new(center: Coordinate, width: Length, height: Length) -> LatLonBox {
+        let center = Point::from(center);
+        LatLonBox {
+            north: center
+                .haversine_destination(0.0, height.get::<meter>() / 2.0)
+                .y(),
+            south: center
+                .haversine_destination(180.0, height.get::<meter>() / 2.0)
+                .y(),
+            east: center
+                .haversine_destination(90.0, width.get::<meter>() / 2.0)
+                .x(),
+            west: center
+                .haversine_destination(270.0, width.get::<meter>() / 2.0)
+                .x(),
+        }
+    }
+
+    pub fn adjust_width(self, at: Coordinate, width: Length) -> LatLonBox {
+        let lon = (self.east + self.west) / 2.0;
+        let angle = Point::from(at)
+            .haversine_destination(90.0, width.get::<meter>() / 2.0)
+            .x()
+            - at.x;
+        LatLonBox {
+            east: lon + angle,
+            west: lon - angle,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Boundary(pub LineString);
+
+impl Boundary {
+    pub fn load(input: impl BufRead) -> Boundary {
+        Boundary(
+            input
+                .lines()
+                .map(|line| line.unwrap())
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| {
+                    line.trim()
+                        .splitn(3, ',')
+                        .take(2)
+                        .filter_map(|f| f.parse().ok())
+                        .collect_tuple::<(f64, f64)>()
+                })
+                .collect(),
+        )
+    }
+
+    /// The smallest box around the boundary (or an empty one at 0, 0 if there are no points).
+    pub fn bounds(&self) -> Rect {
+        self.0.bounding_rect().unwrap_or_else(|| {
+            let origin = Coordinate { x: 0.0, y: 0.0 };
+            Rect::new(origin, origin)
+        })
+    }
+
+    pub fn limit(&self, survey: &Survey) -> Option<Line> {
+        let survey_line = survey.as_line();
+        let (west, east) = self
+            .0
+            .lines()
+            .filter_map(|line| {
+                if let Some(i) = line.intersection(survey_line) {
+                    if line.roughly_contains(i) {
+                        return Some((
+                            i,
+                            OrdF64(Point::from(survey.field).haversine_distance(&i.into())),
+                        ));
+                    }
+                }
+                None
+            })
+            .partition::<Vec<_>, _>(|(intersection, _)| intersection.x < survey.field.x);
+        let (start, end) = vec![west, east]
+            .into_iter()
+            .filter_map(|v| v.into_iter().min_by_key(|(_, d)| *d).map(|(i, _)| i))
+            .collect_tuple()?;
+        Some(Line { start, end })
+    }
+}
@@ -1,286 +1,430 @@
 #![deny(rust_2018_idioms)]
-#![allow(clippy::map_entry)] // https://github.com/rust-lang/rust-clippy/issues/1450
 
-mod geo;
-mod image;
-mod ord;
-mod survey;
-mod template;
-
-use crate::geo::*;
-use crate::ord::OrdF64;
-use crate::survey::Survey;
-use crate::template::*;
-use anyhow::Result;
-use askama::Template;
-use hex::FromHex;
-use itertools::Itertools;
-use lazy_static::lazy_static;
-use std::borrow::Cow;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{prelude::*, BufReader, ErrorKind};
-use std::path::Path;
-use std::process::Command;
+use anyhow::{anyhow, Result};
+use navarro::compare::{self, Comparison};
+use navarro::diff::{self, SurveyDiff};
+use navarro::output::{self, OutputWriter};
+use navarro::{query, report_timings, Data, State};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use uom::si::f64::Length;
-use uom::si::length::{foot, meter};
-use zip::write::{FileOptions, ZipWriter};
-use zip::CompressionMethod;
+use uom::si::length::{kilometer, meter};
+
+/// navarro reads `data/` and `survey/` from, and writes `site/` into, its own checkout.
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
 
 #[cfg(feature = "hotwatch")]
 fn main() -> Result<()> {
-    use hotwatch::blocking::{Flow, Hotwatch};
-    use hotwatch::Event;
+    use hotwatch::{Event, Hotwatch};
+    use navarro::{Change, Server};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
 
-    run()?;
+    if let Some(result) = subcommand() {
+        return result;
+    }
 
-    if std::env::args().any(|arg| arg == "watch") {
-        fn handler(event: Event) -> Flow {
-            eprint!("{:?} ... ", event);
-            if let Err(err) = run() {
-                eprintln!("\n{:?}", err);
-                Flow::Exit
-            } else {
-                eprintln!("done.");
-                Flow::Continue
-            }
+    let timings = std::env::args().any(|arg| arg == "timings");
+    let serve = std::env::args().any(|arg| arg == "serve");
+    if !serve && !std::env::args().any(|arg| arg == "watch") {
+        let start = Instant::now();
+        State::load(root(), outputs()?)?.write()?;
+        if timings {
+            report_timings(start.elapsed());
         }
-
-        let mut hotwatch = Hotwatch::new()?;
-        hotwatch.watch(root().join("survey"), handler).unwrap();
-        hotwatch
-            .watch(root().join("data").join("boundary.kml"), handler)
-            .unwrap();
-        hotwatch
-            .watch(root().join("data").join("teams.csv"), handler)
-            .unwrap();
-        hotwatch.run();
-    } else {
-        run()?;
+        return Ok(());
     }
-    Ok(())
-}
-
-#[cfg(not(feature = "hotwatch"))]
-fn main() -> Result<()> {
-    run()
-}
 
-fn run() -> Result<()> {
-    let revision = match option_env!("COMMIT_REF") {
-        Some(rev) => Cow::from(rev),
-        None => String::from_utf8(
-            Command::new("git")
-                .args(&["rev-parse", "HEAD"])
-                .output()?
-                .stdout,
-        )?
-        .into(),
+    let server = if serve {
+        let port = match std::env::var("PORT") {
+            Ok(port) => port.parse()?,
+            Err(_) => 8020,
+        };
+        Some(Server::start(("127.0.0.1", port), &root().join("site"))?)
+    } else {
+        None
     };
-    let revision = revision.trim();
-
-    let boundary = Boundary::load(BufReader::new(File::open(
-        root().join("data").join("boundary.kml"),
-    )?));
 
-    let mut fields = Vec::new();
-    let mut images = HashMap::new();
-
-    for line in BufReader::new(File::open(root().join("data").join("teams.csv"))?)
-        .lines()
-        .skip(1)
-    {
-        let team = Team::from_str(&line?);
-        let kml = match fs::read_to_string(
-            root().join("survey").join(&team.name).with_extension("kml"),
-        ) {
-            Ok(x) => x,
-            Err(e) if e.kind() == ErrorKind::NotFound => continue,
-            Err(e) => return Err(e.into()),
-        };
-        let survey = survey::default(&kml);
+    // Load each file as its own change, so that one that's broken at startup is reported and left
+    // out until the next change instead of ending the session.
+    let mut state = State::empty(root(), outputs()?)?;
+    let mut pending = rebuild(
+        &mut state,
+        vec![
+            Change::Boundary,
+            Change::Places,
+            Change::States,
+            Change::Story,
+            Change::Teams,
+        ],
+        server.as_ref(),
+        timings,
+    );
 
-        let line = boundary.limit(&survey).unwrap();
-        let center = (line.start + line.end) / 2.0;
+    let (tx, rx) = mpsc::channel();
+    let mut hotwatch = Hotwatch::new()?;
+    for dir in &["survey", "data", "templates"] {
+        let tx = tx.clone();
+        hotwatch.watch(root().join(dir), move |event: Event| {
+            tx.send(event).ok();
+        })?;
+    }
 
-        images.insert(format!("{}.png", team.name), image::label(&team)?);
-        let field_filename = format!("{}.png", hex::encode(team.color));
-        if !images.contains_key(&field_filename) {
-            images.insert(field_filename, image::field(&team)?);
+    while let Ok(event) = rx.recv() {
+        // Editors (and Google Earth) tend to save in bursts; wait for things to settle down.
+        let mut events = vec![event];
+        while let Ok(event) = rx.recv_timeout(Duration::from_millis(500)) {
+            events.push(event);
         }
 
-        let field_length = line
-            .interpolate()
-            .tuple_windows()
-            .map(|(start, end)| Line { start, end }.haversine_length())
-            .sum::<f64>();
-
-        lazy_static! {
-            static ref FIELD_WIDTH: Length = Length::new::<foot>(160.0);
-            static ref LABEL_WIDTH: Length = *FIELD_WIDTH * 500.0;
-            static ref LABEL_HEIGHT: Length = Length::new::<foot>(360.0) * 500.0;
-            static ref LABEL_DIAGONAL: Length = ((*LABEL_HEIGHT).powi(uom::typenum::P2::new())
-                + (*LABEL_WIDTH).powi(uom::typenum::P2::new()))
-            .sqrt();
+        for event in events {
+            eprintln!("{:?}", event);
+            let changes = match event {
+                Event::Create(path) | Event::Write(path) | Event::Remove(path) => {
+                    vec![Change::from_path(&path)]
+                }
+                Event::Rename(from, to) => vec![Change::from_path(&from), Change::from_path(&to)],
+                Event::Rescan => vec![Some(Change::Rescan)],
+                _ => Vec::new(),
+            };
+            for change in changes.into_iter().flatten() {
+                if let Change::Rebuild(path) = &change {
+                    let warning = format!(
+                        "{} is compiled into navarro; restart it to pick up changes",
+                        path.display()
+                    );
+                    eprintln!("{}", warning);
+                    if let Some(server) = &server {
+                        server.error(&warning);
+                    }
+                } else if !pending.contains(&change) {
+                    pending.push(change);
+                }
+            }
+        }
+        if pending.is_empty() {
+            continue;
         }
 
-        fields.push(Field {
-            team,
-            field: LatLonBox::new(center, *FIELD_WIDTH, Length::new::<meter>(field_length))
-                .adjust_width(survey.field, *FIELD_WIDTH),
-            field_bearing: center.bearing_from_slope(line.slope()),
-            line: line.interpolate(),
-            label: LatLonBox::new(survey.field, *LABEL_WIDTH, *LABEL_HEIGHT),
-            label_bearing: survey.bearing,
-            label_region: LatLonBox::new(survey.field, *LABEL_DIAGONAL, *LABEL_DIAGONAL),
-        });
+        pending = rebuild(&mut state, pending, server.as_ref(), timings);
     }
+    Ok(())
+}
 
-    let site_dir = root().join("site");
-    let files_dir = site_dir.join("files");
-    fs::create_dir_all(&files_dir)?;
+/// Applies `pending` and writes the outputs, reporting what failed (on stderr, and to the browser if
+/// serving). Returns the changes to try again after the next one.
+#[cfg(feature = "hotwatch")]
+fn rebuild(
+    state: &mut State,
+    pending: Vec<navarro::Change>,
+    server: Option<&navarro::Server>,
+    timings: bool,
+) -> Vec<navarro::Change> {
+    use std::time::Instant;
 
-    let mut zip = ZipWriter::new(File::create(site_dir.join("20020.kmz"))?);
-    fs::write(
-        site_dir.join("20020.kml"),
-        Output {
-            kmz: false,
-            revision: &revision,
-            fields: &fields,
+    eprint!("rebuilding {:?} ... ", pending);
+    let start = Instant::now();
+    match state.try_update(&pending) {
+        Ok(failed) => {
+            eprintln!("done.");
+            if timings {
+                report_timings(start.elapsed());
+            }
+            match server {
+                Some(server) if failed.len() < pending.len() => server.reload(),
+                _ => {}
+            }
+            if !failed.is_empty() {
+                let errors = failed
+                    .iter()
+                    .map(|(change, err)| format!("{:?}: {:?}", change, err))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                eprintln!("{}\nleaving those out until the next change", errors);
+                if let Some(server) = server {
+                    server.error(&errors);
+                }
+            }
+            failed.into_iter().map(|(change, _)| change).collect()
         }
-        .render()?
-        .as_bytes(),
-    )?;
-    zip.start_file("doc.kml", FileOptions::default())?;
-    zip.write_all(
-        Output {
-            kmz: true,
-            revision: &revision,
-            fields: &fields,
+        Err(err) => {
+            eprintln!(
+                "\n{:?}\nkeeping the last good output until the next change",
+                err
+            );
+            if let Some(server) = server {
+                server.error(&format!("{:?}", err));
+            }
+            pending
         }
-        .render()?
-        .as_bytes(),
-    )?;
+    }
+}
 
-    for (filename, image) in images {
-        zip.start_file(
-            &format!("files/{}", filename),
-            FileOptions::default().compression_method(CompressionMethod::Stored),
-        )?;
-        zip.write_all(&image)?;
+#[cfg(not(feature = "hotwatch"))]
+fn main() -> Result<()> {
+    if let Some(result) = subcommand() {
+        return result;
     }
 
-    zip.finish()?;
+    let start = std::time::Instant::now();
+    State::load(root(), outputs()?)?.write()?;
+    if std::env::args().any(|arg| arg == "timings") {
+        report_timings(start.elapsed());
+    }
     Ok(())
 }
 
-fn root() -> &'static Path {
-    Path::new(env!("CARGO_MANIFEST_DIR"))
+/// Outputs selected with `--outputs=kml,kmz,...`.
+fn outputs() -> Result<Vec<Arc<dyn OutputWriter>>> {
+    let names = std::env::args()
+        .find_map(|arg| arg.strip_prefix("--outputs=").map(str::to_owned))
+        .unwrap_or_else(|| output::DEFAULT.join(","));
+    names
+        .split(',')
+        .map(|name| output::by_name(name).ok_or_else(|| anyhow!("unknown output {:?}", name)))
+        .collect()
 }
 
-// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
-
-#[derive(Debug)]
-struct Team {
-    name: String,
-    abbr: String,
-    color: [u8; 3],
+/// Runs a subcommand (`query`, `diff`, `synth` or `compare`), if that's what was asked for.
+fn subcommand() -> Option<Result<()>> {
+    match std::env::args().nth(1).as_deref() {
+        Some("query") => Some(query()),
+        Some("diff") => Some(diff()),
+        #[cfg(feature = "synthetic")]
+        Some("synth") => Some(synth()),
+        Some("compare") => Some(compare()),
+        _ => None,
+    }
 }
 
-impl Team {
-    fn from_str(s: &str) -> Team {
-        let mut iter = s.split(',');
-        Team {
-            name: iter.next().unwrap().to_string(),
-            abbr: iter.next().unwrap().to_string(),
-            color: <[u8; 3]>::from_hex(iter.next().unwrap().trim_start_matches('#')).unwrap(),
-        }
+/// `navarro query <lon,lat | place> [--within=KM]`: which lines pass near a point.
+fn query() -> Result<()> {
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    let within = match args.iter().find_map(|arg| arg.strip_prefix("--within=")) {
+        Some(km) => km.parse()?,
+        None => 25.0,
+    };
+    let input = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if input.is_empty() {
+        return Err(anyhow!(
+            "usage: navarro query <lon,lat | place> [--within=KM]"
+        ));
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-struct LatLonBox {
-    north: f64,
-    south: f64,
-    east: f64,
-    west: f64,
+    let state = State::load(root(), Vec::new())?;
+    let point = query::locate(state.places(), &input)?;
+    let fields = state.fields();
+    let nearby = query::query(&fields, point, Length::new::<kilometer>(within));
+    println!(
+        "{} line(s) within {} km of {} ({:.5}, {:.5})",
+        nearby.len(),
+        within,
+        input,
+        point.x,
+        point.y
+    );
+    for nearby in nearby {
+        println!(
+            "{:>8.1} km  {:5}  {} ({} side)",
+            nearby.distance.get::<kilometer>(),
+            nearby.field.team.abbr,
+            nearby.field.team.name,
+            nearby.side
+        );
+    }
+    Ok(())
 }
 
-impl LatLonBox {
-    fn new(center: Coordinate, width: Length, height: Length) -> LatLonBox {
-        let center = Point::from(center);
-        LatLonBox {
-            north: center
-                .haversine_destination(0.0, height.get::<meter>() / 2.0)
-                .y(),
-            south: center
-                .haversine_destination(180.0, height.get::<meter>() / 2.0)
-                .y(),
-            east: center
-                .haversine_destination(90.0, width.get::<meter>() / 2.0)
-                .x(),
-            west: center
-                .haversine_destination(270.0, width.get::<meter>() / 2.0)
-                .x(),
-        }
+/// `navarro diff [OLD] NEW [--output=FILE]`: how a survey moved between two versions, each either a
+/// file or a `revision:path` git object. OLD defaults to NEW as of `HEAD`.
+fn diff() -> Result<()> {
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    let output = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--output="))
+        .map_or_else(|| root().join("site").join("diff.kml"), PathBuf::from);
+    let (old, new) = match args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [new] => (format!("HEAD:./{}", new), new.to_string()),
+        [old, new] => (old.to_string(), new.to_string()),
+        _ => return Err(anyhow!("usage: navarro diff [OLD] NEW [--output=FILE]")),
+    };
+
+    let diff = SurveyDiff::new(
+        &diff::read(&old)?,
+        &diff::read(&new)?,
+        &Data::load(root())?.boundary,
+    );
+    println!(
+        "field moved {:.1} m and turned {:+.2}°",
+        diff.shift.get::<meter>(),
+        diff.rotation
+    );
+    match diff.endpoint_shifts() {
+        Some((west, east)) => println!(
+            "line's west end moved {:.1} km, east end moved {:.1} km",
+            west.get::<kilometer>(),
+            east.get::<kilometer>()
+        ),
+        None => println!("line doesn't reach the boundary in both versions"),
     }
 
-    fn adjust_width(self, at: Coordinate, width: Length) -> LatLonBox {
-        let lon = (self.east + self.west) / 2.0;
-        let angle = Point::from(at)
-            .haversine_destination(90.0, width.get::<meter>() / 2.0)
-            .x()
-            - at.x;
-        LatLonBox {
-            east: lon + angle,
-            west: lon - angle,
-            ..self
+    let name = Path::new(new.rsplit(':').next().unwrap_or(&new))
+        .file_stem()
+        .map_or_else(|| new.clone(), |stem| stem.to_string_lossy().into_owned());
+    std::fs::write(&output, diff.kml(&name, &old, &new)?)?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+/// `navarro synth METHOD --at=LON,LAT [--bearing=DEG] [--noise=M] [--seed=N] [--trials=N]`: prints
+/// a synthetic survey KML, or with `--trials`, how accurate the method (or `all` of them) is
+/// under that much noise.
+#[cfg(feature = "synthetic")]
+fn synth() -> Result<()> {
+    use navarro::{synthetic, Method};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    let flag = |name: &str| {
+        args.iter()
+            .find_map(|arg| arg.strip_prefix(&format!("--{}=", name)))
+    };
+    let usage = || {
+        anyhow!(
+            "usage: navarro synth METHOD --at=LON,LAT [--bearing=DEG] [--noise=M] [--seed=N] \
+             [--trials=N]"
+        )
+    };
+
+    let field = match flag("at").map(|at| query::locate(&[], at)) {
+        Some(field) => field?,
+        None => return Err(usage()),
+    };
+    let bearing = flag("bearing").map_or(Ok(0.0), str::parse)?;
+    let noise = Length::new::<meter>(flag("noise").map_or(Ok(0.0), str::parse)?);
+    let mut rng = match flag("seed") {
+        Some(seed) => StdRng::seed_from_u64(seed.parse()?),
+        None => StdRng::from_entropy(),
+    };
+    let methods = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(method) if method == "all" => Method::ALL.to_vec(),
+        Some(method) => vec![method.parse()?],
+        None => return Err(usage()),
+    };
+
+    match flag("trials") {
+        Some(trials) => {
+            let trials = trials.parse()?;
+            println!(
+                "RMS error over {} trials with {} m of noise:",
+                trials,
+                noise.get::<meter>()
+            );
+            for method in methods {
+                let (distance, angle) =
+                    synthetic::accuracy(field, bearing, method, noise, trials, &mut rng)?;
+                println!(
+                    "{:>16}  {:>8.3} m  {:>7.4}°",
+                    method,
+                    distance.get::<meter>(),
+                    angle
+                );
+            }
         }
+        None => match methods.as_slice() {
+            &[method] => print!(
+                "{}",
+                synthetic::generate(field, bearing, method, noise, &mut rng)?
+            ),
+            _ => return Err(usage()),
+        },
     }
+    Ok(())
 }
 
-#[derive(Debug)]
-struct Boundary(LineString);
+/// `navarro compare [FILE | TEAM]... [--output=FILE]`: runs every method that fits each survey
+/// (every survey, by default), with and without `centerfit`, and prints how far apart they are and
+/// which to use.
+fn compare() -> Result<()> {
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    let output = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--output="))
+        .map_or_else(|| root().join("site").join("compare.kml"), PathBuf::from);
+    let mut paths = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .map(|arg| {
+            if Path::new(arg).is_file() {
+                Path::new(arg).to_path_buf()
+            } else {
+                root().join("survey").join(arg).with_extension("kml")
+            }
+        })
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        for entry in std::fs::read_dir(root().join("survey"))? {
+            let path = entry?.path();
+            if path.extension() == Some("kml".as_ref()) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+    }
 
-impl Boundary {
-    fn load(input: impl BufRead) -> Boundary {
-        Boundary(
-            input
-                .lines()
-                .map(|line| line.unwrap())
-                .filter(|line| !line.starts_with('#'))
-                .filter_map(|line| {
-                    line.trim()
-                        .splitn(3, ',')
-                        .take(2)
-                        .filter_map(|f| f.parse().ok())
-                        .collect_tuple::<(f64, f64)>()
-                })
-                .collect(),
-        )
+    let mut comparisons = Vec::new();
+    for path in &paths {
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let kml = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("can't read {}: {}", path.display(), err))?;
+        match Comparison::new(&kml) {
+            Some(comparison) => comparisons.push((name, comparison)),
+            None => println!("{}: no method fits\n", name),
+        }
     }
 
-    fn limit(&self, survey: &Survey) -> Option<Line> {
-        let survey_line = survey.as_line();
-        let (west, east) = self
-            .0
-            .lines()
-            .filter_map(|line| {
-                if let Some(i) = line.intersection(survey_line) {
-                    if line.roughly_contains(i) {
-                        return Some((
-                            i,
-                            OrdF64(Point::from(survey.field).haversine_distance(&i.into())),
-                        ));
-                    }
-                }
-                None
-            })
-            .partition::<Vec<_>, _>(|(intersection, _)| intersection.x < survey.field.x);
-        let (start, end) = vec![west, east]
-            .into_iter()
-            .filter_map(|v| v.into_iter().min_by_key(|(_, d)| *d).map(|(i, _)| i))
-            .collect_tuple()?;
-        Some(Line { start, end })
+    for (name, comparison) in &comparisons {
+        println!(
+            "{}: fields up to {:.2} m and headings up to {:.3}° apart",
+            name,
+            comparison.location_spread().get::<meter>(),
+            comparison.heading_spread()
+        );
+        for (i, estimate) in comparison.estimates.iter().enumerate() {
+            let mut marks = Vec::new();
+            if comparison.default == Some(i) {
+                marks.push("default");
+            }
+            if comparison.recommended() == Some(i) {
+                marks.push("recommended");
+            }
+            println!(
+                "  {:>28}  {:>8.2} m  {:>7.3}°  {}",
+                estimate.name(),
+                estimate.distance.get::<meter>(),
+                estimate.turn,
+                marks.join(", ")
+            );
+        }
+        println!();
     }
+
+    std::fs::write(&output, compare::kml(&comparisons)?)?;
+    println!("wrote {}", output.display());
+    Ok(())
 }
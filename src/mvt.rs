@@ -0,0 +1,223 @@
+use crate::geo::*;
+use crate::output::{write_dir_atomic, OutputWriter, Site};
+use crate::template::Field;
+use crate::tiles::{clip, covering, mercator, tile};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+const EXTENT: u32 = 4096;
+/// How far past the edge of a tile geometry is kept, in tile units, so that clients can draw
+/// lines across tile boundaries without seams.
+const BUFFER: f64 = 64.0 / EXTENT as f64;
+pub(crate) const MIN_ZOOM: u8 = 3;
+pub(crate) const MAX_ZOOM: u8 = 12;
+
+/// Encodes every tile with something on it, as `(zoom, x, y)` and the tile's bytes.
+pub fn tiles(fields: &[&Field]) -> BTreeMap<(u8, u32, u32), Vec<u8>> {
+    let mut tiles = BTreeMap::new();
+    for zoom in MIN_ZOOM..=MAX_ZOOM {
+        let lines = fields
+            .iter()
+            .map(|field| field.line.map(|c| mercator(c, zoom)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut coverage = BTreeMap::<(u32, u32), BTreeSet<usize>>::new();
+        for (i, (field, line)) in fields.iter().zip(&lines).enumerate() {
+            let mut covered = BTreeSet::new();
+            covering(line, zoom, BUFFER, &mut covered);
+            covered.insert(tile(mercator(field.stadium, zoom), zoom));
+            for xy in covered {
+                coverage.entry(xy).or_default().insert(i);
+            }
+        }
+
+        for ((x, y), indices) in coverage {
+            let origin = Coordinate::from((f64::from(x), f64::from(y)));
+            let mut lines_layer = Layer::new("lines");
+            let mut stadiums_layer = Layer::new("stadiums");
+            for i in indices {
+                let field = fields[i];
+                let runs = clip(&lines[i], x, y, BUFFER);
+                if !runs.is_empty() {
+                    lines_layer.add(field, 2, &line_geometry(&runs));
+                }
+                let stadium = mercator(field.stadium, zoom) - origin;
+                if (0.0..1.0).contains(&stadium.x) && (0.0..1.0).contains(&stadium.y) {
+                    stadiums_layer.add(field, 1, &point_geometry(stadium));
+                }
+            }
+
+            let mut tile = Vec::new();
+            for layer in &[lines_layer, stadiums_layer] {
+                if !layer.features.is_empty() {
+                    message(&mut tile, 3, &layer.encode());
+                }
+            }
+            if !tile.is_empty() {
+                tiles.insert((zoom, x, y), tile);
+            }
+        }
+    }
+    tiles
+}
+
+/// `mvt/{z}/{x}/{y}.pbf`: Mapbox Vector Tiles with a `lines` and a `stadiums` layer, each feature
+/// tagged with `team`, `abbr` and `color`.
+pub struct Mvt;
+
+impl OutputWriter for Mvt {
+    fn name(&self) -> &'static str {
+        "mvt"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        write_dir_atomic(&dir.join("mvt"), |tmp_dir| {
+            for ((z, x, y), tile) in tiles(site.fields) {
+                let path = tmp_dir
+                    .join(z.to_string())
+                    .join(x.to_string())
+                    .join(format!("{}.pbf", y));
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(path, tile)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+struct Layer {
+    name: &'static str,
+    features: Vec<u8>,
+    values: Vec<String>,
+}
+
+impl Layer {
+    const KEYS: [&'static str; 3] = ["team", "abbr", "color"];
+
+    fn new(name: &'static str) -> Layer {
+        Layer {
+            name,
+            features: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, field: &Field, geom_type: u64, geometry: &[u32]) {
+        let values = [
+            field.team.name.clone(),
+            field.team.abbr.clone(),
+            format!("#{}", hex::encode(field.team.color)),
+        ];
+        let mut tags = Vec::new();
+        for (key, value) in values.iter().enumerate() {
+            let index = match self.values.iter().position(|v| v == value) {
+                Some(index) => index,
+                None => {
+                    self.values.push(value.clone());
+                    self.values.len() - 1
+                }
+            };
+            tags.extend_from_slice(&[key as u32, index as u32]);
+        }
+
+        let mut feature = Vec::new();
+        packed(&mut feature, 2, &tags);
+        varint_field(&mut feature, 3, geom_type);
+        packed(&mut feature, 4, geometry);
+        message(&mut self.features, 2, &feature);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut layer = Vec::new();
+        varint_field(&mut layer, 15, 2);
+        message(&mut layer, 1, self.name.as_bytes());
+        layer.extend_from_slice(&self.features);
+        for key in &Layer::KEYS {
+            message(&mut layer, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            let mut encoded = Vec::new();
+            message(&mut encoded, 1, value.as_bytes());
+            message(&mut layer, 4, &encoded);
+        }
+        varint_field(&mut layer, 5, u64::from(EXTENT));
+        layer
+    }
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn scale(c: Coordinate) -> (i32, i32) {
+    (
+        (c.x * f64::from(EXTENT)).round() as i32,
+        (c.y * f64::from(EXTENT)).round() as i32,
+    )
+}
+
+fn point_geometry(c: Coordinate) -> Vec<u32> {
+    let (x, y) = scale(c);
+    vec![command(1, 1), zigzag(x), zigzag(y)]
+}
+
+fn line_geometry(runs: &[Vec<Coordinate>]) -> Vec<u32> {
+    let mut geometry = Vec::new();
+    let mut cursor = (0, 0);
+    for run in runs {
+        let mut points = run.iter().map(|&c| scale(c)).collect::<Vec<_>>();
+        points.dedup();
+        if points.len() < 2 {
+            continue;
+        }
+        let mut delta = |(x, y): (i32, i32)| {
+            let d = [zigzag(x - cursor.0), zigzag(y - cursor.1)];
+            cursor = (x, y);
+            d
+        };
+        geometry.push(command(1, 1));
+        geometry.extend_from_slice(&delta(points[0]));
+        geometry.push(command(2, points.len() - 1));
+        for &point in &points[1..] {
+            geometry.extend_from_slice(&delta(point));
+        }
+    }
+    geometry
+}
+
+// Just enough protobuf to write vector tiles.
+
+pub(crate) fn varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn varint_field(buf: &mut Vec<u8>, field: u32, n: u64) {
+    varint(buf, u64::from(field) << 3);
+    varint(buf, n);
+}
+
+fn message(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    varint(buf, (u64::from(field) << 3) | 2);
+    varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut encoded = Vec::new();
+    for &value in values {
+        varint(&mut encoded, u64::from(value));
+    }
+    message(buf, field, &encoded);
+}
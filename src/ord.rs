@@ -26,7 +26,7 @@ impl Eq for OrdF64 {}
 
 impl PartialOrd for OrdF64 {
     fn partial_cmp(&self, other: &OrdF64) -> Option<Ordering> {
-        self.0.partial_cmp(&other.0)
+        Some(self.cmp(other))
     }
 }
 
@@ -0,0 +1,328 @@
+use crate::gazetteer::{Gazetteers, Place, StateBoundary, StateLengths};
+use crate::intersections::Intersections;
+use crate::mvt::Mvt;
+use crate::pmtiles::Pmtiles;
+use crate::poster::Poster;
+use crate::story::{events, Story};
+use crate::template::{Field, GeoJson as GeoJsonTemplate, Output};
+use crate::template::{Index as IndexTemplate, Leaflet as LeafletTemplate, Split as SplitTemplate};
+use crate::territory::Territories;
+use crate::tiles::Tiles;
+use crate::{Boundary, Team};
+use anyhow::{anyhow, Result};
+use askama::Template;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use zip::write::{FileOptions, ZipWriter};
+use zip::{CompressionMethod, DateTime};
+
+/// Everything computed for the map, as handed to each output.
+#[derive(Debug, Clone, Copy)]
+pub struct Site<'a> {
+    pub revision: &'a str,
+    /// Seconds since the epoch to stamp files with, so that output is reproducible.
+    pub timestamp: i64,
+    pub boundary: &'a Boundary,
+    /// Cities and towns from `places.csv`, for describing where things are.
+    pub places: &'a [Place],
+    /// State outlines from `states.kml`, if there is one.
+    pub states: &'a [StateBoundary],
+    /// Events from `story.csv`.
+    pub story: &'a [Story],
+    /// Every team in `teams.csv`, including those without a field yet.
+    pub teams: &'a [Team],
+    pub fields: &'a [&'a Field],
+    /// Rendered PNGs keyed by filename.
+    pub images: &'a BTreeMap<String, Vec<u8>>,
+}
+
+pub trait OutputWriter: Send + Sync {
+    /// The name used to select this output with `--outputs`.
+    fn name(&self) -> &'static str;
+
+    /// Writes this output into `dir` (usually `site/`).
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()>;
+}
+
+pub const DEFAULT: &[&str] = &["kml", "kmz", "index"];
+
+pub fn by_name(name: &str) -> Option<Arc<dyn OutputWriter>> {
+    Some(match name {
+        "kml" => Arc::new(Kml),
+        "kmz" => Arc::new(Kmz),
+        "split" => Arc::new(Split),
+        "geojson" => Arc::new(GeoJson),
+        "index" => Arc::new(Index),
+        "leaflet" => Arc::new(Leaflet),
+        "poster" => Arc::new(Poster),
+        "intersections" => Arc::new(Intersections),
+        "territories" => Arc::new(Territories),
+        "gazetteer" => Arc::new(Gazetteers),
+        "states" => Arc::new(StateLengths),
+        "tiles" => Arc::new(Tiles::default()),
+        "mvt" => Arc::new(Mvt),
+        "pmtiles" => Arc::new(Pmtiles),
+        _ => return None,
+    })
+}
+
+/// Writes to a temporary file first, so that a failed build leaves the previous output in place.
+pub(crate) fn write_atomic(path: &Path, f: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let tmp = path.with_extension(match path.extension() {
+        Some(ext) => format!("{}.tmp", ext.to_string_lossy()),
+        None => "tmp".into(),
+    });
+    f(&mut File::create(&tmp)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Has `f` fill a new directory beside `path`, then swaps it in, so that a failed build leaves the
+/// previous directory in place (and a successful one leaves no stale files behind).
+pub(crate) fn write_dir_atomic(path: &Path, f: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp)?;
+    }
+    fs::create_dir_all(&tmp)?;
+    f(&tmp)?;
+    if path.exists() {
+        fs::remove_dir_all(path)?;
+    }
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// A quoted CSV field, so that names with commas (or quotes) stay in their column.
+pub(crate) fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// `timestamp` (in seconds since the epoch) as a UTC date and time, the way ZIP files store them.
+fn zip_time(timestamp: i64) -> Result<DateTime> {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, seconds) = (timestamp.div_euclid(86_400), timestamp.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let out_of_range = || anyhow!("timestamp {} out of range for zip", timestamp);
+    DateTime::from_date_and_time(
+        u16::try_from(year).map_err(|_| out_of_range())?,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .map_err(|()| out_of_range())
+}
+
+/// The images that `20020.kml` uses for a field, as keys into `Site::images`.
+fn images(field: &Field) -> Vec<String> {
+    vec![
+        format!("{}.png", field.team.name),
+        format!("{}.png", hex::encode(field.team.color)),
+    ]
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// `20020.kml`, which only has the lines (images can't be referenced without a KMZ).
+pub struct Kml;
+
+impl OutputWriter for Kml {
+    fn name(&self) -> &'static str {
+        "kml"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let story = events(site.story, site.fields);
+        let kml = Output {
+            kmz: false,
+            linked: false,
+            revision: site.revision,
+            states: site.states,
+            story: &story,
+            fields: site.fields,
+        }
+        .render()?;
+        write_atomic(&dir.join("20020.kml"), |file| {
+            Ok(file.write_all(kml.as_bytes())?)
+        })
+    }
+}
+
+/// `20020.kmz`, including the labels and fields.
+pub struct Kmz;
+
+impl OutputWriter for Kmz {
+    fn name(&self) -> &'static str {
+        "kmz"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let options = FileOptions::default().last_modified_time(zip_time(site.timestamp)?);
+        let story = events(site.story, site.fields);
+        let kml = Output {
+            kmz: true,
+            linked: false,
+            revision: site.revision,
+            states: site.states,
+            story: &story,
+            fields: site.fields,
+        }
+        .render()?;
+
+        write_atomic(&dir.join("20020.kmz"), |file| {
+            let mut zip = ZipWriter::new(file);
+            zip.start_file("doc.kml", options)?;
+            zip.write_all(kml.as_bytes())?;
+
+            let used = site
+                .fields
+                .iter()
+                .flat_map(|field| images(field))
+                .collect::<HashSet<_>>();
+            for (filename, image) in site.images {
+                if !used.contains(filename) {
+                    continue;
+                }
+                zip.start_file(
+                    format!("files/{}", filename),
+                    options.compression_method(CompressionMethod::Stored),
+                )?;
+                zip.write_all(image)?;
+            }
+
+            zip.finish()?;
+            Ok(())
+        })
+    }
+}
+
+/// `20020-split.kml`, which loads each team from `teams/{name}.kml` (with images in
+/// `teams/files/`) through a `<NetworkLink>` once the team's line is on screen, so that clients only
+/// fetch the teams near the camera, and a change to one team only changes one small file.
+pub struct Split;
+
+impl OutputWriter for Split {
+    fn name(&self) -> &'static str {
+        "split"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let story = events(site.story, site.fields);
+        write_dir_atomic(&dir.join("teams"), |tmp_dir| {
+            fs::create_dir_all(tmp_dir.join("files"))?;
+            for field in site.fields {
+                let story = story
+                    .iter()
+                    .filter(|event| event.story.teams.contains(&field.team.name))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let kml = Output {
+                    kmz: true,
+                    linked: true,
+                    revision: site.revision,
+                    states: &[],
+                    story: &story,
+                    fields: &[field],
+                }
+                .render()?;
+                fs::write(
+                    tmp_dir.join(format!("{}.kml", field.team.name)),
+                    kml.as_bytes(),
+                )?;
+                for filename in images(field) {
+                    fs::write(
+                        tmp_dir.join("files").join(&filename),
+                        &site.images[&filename],
+                    )?;
+                }
+            }
+            Ok(())
+        })?;
+
+        let kml = SplitTemplate {
+            revision: site.revision,
+            fields: site.fields,
+        }
+        .render()?;
+        write_atomic(&dir.join("20020-split.kml"), |file| {
+            Ok(file.write_all(kml.as_bytes())?)
+        })
+    }
+}
+
+/// `20020.geojson`, with a LineString and a stadium Point for each field.
+pub struct GeoJson;
+
+impl OutputWriter for GeoJson {
+    fn name(&self) -> &'static str {
+        "geojson"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let geojson = GeoJsonTemplate {
+            revision: site.revision,
+            fields: site.fields,
+        }
+        .render()?;
+        write_atomic(&dir.join("20020.geojson"), |file| {
+            Ok(file.write_all(geojson.as_bytes())?)
+        })
+    }
+}
+
+/// `index.html`, the Cesium map of `20020.kml`.
+pub struct Index;
+
+impl OutputWriter for Index {
+    fn name(&self) -> &'static str {
+        "index"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let html = IndexTemplate {
+            revision: site.revision,
+        }
+        .render()?;
+        write_atomic(&dir.join("index.html"), |file| {
+            Ok(file.write_all(html.as_bytes())?)
+        })
+    }
+}
+
+/// `leaflet.html`, a lighter 2D map of `20020.geojson` (which it also writes) with a legend, a team
+/// search box, and `#team=` links.
+pub struct Leaflet;
+
+impl OutputWriter for Leaflet {
+    fn name(&self) -> &'static str {
+        "leaflet"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        GeoJson.write(site, dir)?;
+        let html = LeafletTemplate {
+            revision: site.revision,
+            teams: site.teams,
+            fields: site.fields,
+        }
+        .render()?;
+        write_atomic(&dir.join("leaflet.html"), |file| {
+            Ok(file.write_all(html.as_bytes())?)
+        })
+    }
+}
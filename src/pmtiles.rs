@@ -0,0 +1,186 @@
+use crate::mvt::{self, varint, MAX_ZOOM, MIN_ZOOM};
+use crate::output::{write_atomic, OutputWriter, Site};
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER_LEN: usize = 127;
+/// Readers fetch the header and root directory with a single 16 KiB request.
+const ROOT_LEN: usize = 16384 - HEADER_LEN;
+
+/// `20020.pmtiles`: the vector tiles from [`mvt::Mvt`] packed into a single [PMTiles v3][spec]
+/// archive, which can be served as a static file.
+///
+/// [spec]: https://github.com/protomaps/PMTiles/blob/main/spec/v3/spec.md
+pub struct Pmtiles;
+
+impl OutputWriter for Pmtiles {
+    fn name(&self) -> &'static str {
+        "pmtiles"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let mut tiles = mvt::tiles(site.fields)
+            .into_iter()
+            .map(|((z, x, y), tile)| (tile_id(z, x, y), tile))
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(id, _)| *id);
+
+        let mut entries = Vec::with_capacity(tiles.len());
+        let mut data = Vec::new();
+        for (tile_id, tile) in &tiles {
+            entries.push(Entry {
+                tile_id: *tile_id,
+                offset: data.len() as u64,
+                length: tile.len() as u64,
+                run_length: 1,
+            });
+            data.extend_from_slice(tile);
+        }
+        let (root, leaves) = directories(&entries);
+
+        let metadata = format!(
+            r#"{{"name":"20020","description":"{}","vector_layers":[{},{}]}}"#,
+            site.revision,
+            layer("lines"),
+            layer("stadiums"),
+        );
+
+        let bounds = site.boundary.bounds();
+        let (west, south) = bounds.min().x_y();
+        let (east, north) = bounds.max().x_y();
+        let e7 = |degrees: f64| ((degrees * 1e7).round() as i32).to_le_bytes();
+
+        let root_offset = HEADER_LEN as u64;
+        let metadata_offset = root_offset + root.len() as u64;
+        let leaves_offset = metadata_offset + metadata.len() as u64;
+        let data_offset = leaves_offset + leaves.len() as u64;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(b"PMTiles\x03");
+        for n in &[
+            root_offset,
+            root.len() as u64,
+            metadata_offset,
+            metadata.len() as u64,
+            leaves_offset,
+            leaves.len() as u64,
+            data_offset,
+            data.len() as u64,
+            entries.len() as u64,
+            entries.len() as u64,
+            entries.len() as u64,
+        ] {
+            header.extend_from_slice(&n.to_le_bytes());
+        }
+        // Clustered, no internal compression, no tile compression, MVT tiles.
+        header.extend_from_slice(&[1, 1, 1, 1, MIN_ZOOM, MAX_ZOOM]);
+        for &degrees in &[west, south, east, north] {
+            header.extend_from_slice(&e7(degrees));
+        }
+        header.push(MIN_ZOOM);
+        header.extend_from_slice(&e7((west + east) / 2.0));
+        header.extend_from_slice(&e7((south + north) / 2.0));
+        debug_assert_eq!(header.len(), HEADER_LEN);
+
+        write_atomic(&dir.join("20020.pmtiles"), |file| {
+            for part in &[&header, &root, metadata.as_bytes(), &leaves, &data] {
+                file.write_all(part)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn layer(id: &str) -> String {
+    format!(
+        r#"{{"id":"{}","fields":{{"team":"String","abbr":"String","color":"String"}},"minzoom":{},"maxzoom":{}}}"#,
+        id, MIN_ZOOM, MAX_ZOOM
+    )
+}
+
+/// Tile IDs number every tile at lower zooms first, then along a Hilbert curve within the zoom.
+fn tile_id(zoom: u8, x: u32, y: u32) -> u64 {
+    let base = (0..zoom).map(|z| 1u64 << (2 * z)).sum::<u64>();
+    let n = 1u64 << zoom;
+    let (mut x, mut y) = (u64::from(x), u64::from(y));
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    base + d
+}
+
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    /// How many consecutive tile IDs share this data, or 0 if this entry points to a leaf
+    /// directory.
+    run_length: u64,
+}
+
+/// Serializes the root directory, splitting entries into leaf directories if there are too many
+/// to fit.
+fn directories(entries: &[Entry]) -> (Vec<u8>, Vec<u8>) {
+    let root = directory(entries);
+    if root.len() <= ROOT_LEN {
+        return (root, Vec::new());
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut root_entries = Vec::new();
+        let mut leaves = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = directory(chunk);
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u64,
+                run_length: 0,
+            });
+            leaves.extend_from_slice(&leaf);
+        }
+        let root = directory(&root_entries);
+        if root.len() <= ROOT_LEN {
+            return (root, leaves);
+        }
+        leaf_size *= 2;
+    }
+}
+
+fn directory(entries: &[Entry]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    varint(&mut buf, entries.len() as u64);
+    let mut last_id = 0;
+    for entry in entries {
+        varint(&mut buf, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        varint(&mut buf, entry.run_length);
+    }
+    for entry in entries {
+        varint(&mut buf, entry.length);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        // 0 means "right after the previous entry".
+        match i.checked_sub(1).map(|prev| &entries[prev]) {
+            Some(prev) if entry.offset == prev.offset + prev.length => varint(&mut buf, 0),
+            _ => varint(&mut buf, entry.offset + 1),
+        }
+    }
+    buf
+}
@@ -0,0 +1,96 @@
+use crate::geo::*;
+use crate::image;
+use crate::output::{write_atomic, OutputWriter, Site};
+use crate::template::{Poster as PosterTemplate, PosterField};
+use anyhow::{anyhow, Result};
+use askama::Template;
+use std::io::Write;
+use std::path::Path;
+
+const WIDTH: f64 = 3000.0;
+const MARGIN: f64 = 60.0;
+/// As large as the PNG can be: tiny-skia 0.3 silently skips fills on pixmaps more than 8192 pixels
+/// across (the limit of its fixed-point rasterizer), leaving only the strokes.
+const PNG_ZOOM: f32 = 2.5;
+
+/// Albers equal-area conic projection with the usual parameters for the contiguous US (standard
+/// parallels 29.5° and 45.5°, origin 23°N 96°W), on a unit sphere. `y` increases northward.
+pub fn albers(point: Coordinate) -> Coordinate {
+    let (phi_1, phi_2) = (29.5_f64.to_radians(), 45.5_f64.to_radians());
+    let (phi_0, lambda_0) = (23.0_f64.to_radians(), (-96.0_f64).to_radians());
+
+    let n = (phi_1.sin() + phi_2.sin()) / 2.0;
+    let c = phi_1.cos().powi(2) + 2.0 * n * phi_1.sin();
+    let rho = |phi: f64| (c - 2.0 * n * phi.sin()).sqrt() / n;
+
+    let theta = n * (point.x.to_radians() - lambda_0);
+    let rho_p = rho(point.y.to_radians());
+    Coordinate {
+        x: rho_p * theta.sin(),
+        y: rho(phi_0) - rho_p * theta.cos(),
+    }
+}
+
+/// `poster.svg` and `poster.png`: a printable map of the boundary, every line, and a labeled dot
+/// for every stadium.
+pub struct Poster;
+
+impl OutputWriter for Poster {
+    fn name(&self) -> &'static str {
+        "poster"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let bounds = site
+            .boundary
+            .0
+            .points_iter()
+            .map(|p| albers(p.0))
+            .collect::<LineString>()
+            .bounding_rect()
+            .ok_or_else(|| anyhow!("boundary is empty"))?;
+        let (min, max) = (bounds.min(), bounds.max());
+        let scale = (WIDTH - 2.0 * MARGIN) / (max.x - min.x);
+        let height = ((max.y - min.y) * scale + 2.0 * MARGIN).ceil();
+        let project = |c: Coordinate| {
+            let c = albers(c);
+            Coordinate {
+                x: MARGIN + (c.x - min.x) * scale,
+                y: MARGIN + (max.y - c.y) * scale,
+            }
+        };
+        let points = |iter: &mut dyn Iterator<Item = Coordinate>| {
+            iter.map(|c| {
+                let c = project(c);
+                format!("{:.1},{:.1}", c.x, c.y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+        };
+
+        let svg = PosterTemplate {
+            revision: site.revision,
+            width: WIDTH,
+            height,
+            boundary: points(&mut site.boundary.0.points_iter().map(|p| p.0)),
+            fields: site
+                .fields
+                .iter()
+                .map(|field| PosterField {
+                    team: &field.team,
+                    line: points(&mut field.line.clone()),
+                    stadium: project(field.stadium),
+                })
+                .collect(),
+        }
+        .render()?;
+
+        write_atomic(&dir.join("poster.svg"), |file| {
+            Ok(file.write_all(svg.as_bytes())?)
+        })?;
+        // oxipng takes far too long on an image this size, and it's not going over the network
+        // with every page load.
+        let png = image::render(&svg, WIDTH as u32, height as u32, PNG_ZOOM)?.encode_png()?;
+        write_atomic(&dir.join("poster.png"), |file| Ok(file.write_all(&png)?))
+    }
+}
@@ -0,0 +1,136 @@
+use crate::gazetteer::Place;
+use crate::geo::*;
+use crate::template::Field;
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use std::fmt;
+use uom::si::f64::Length;
+use uom::si::length::meter;
+
+/// A line passing near the queried point.
+#[derive(Debug, Clone)]
+pub struct Nearby<'a> {
+    pub field: &'a Field,
+    /// Shortest distance from the point to the line.
+    pub distance: Length,
+    /// Which side of the line the point is on.
+    pub side: Side,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::North => "north",
+            Side::East => "east",
+            Side::South => "south",
+            Side::West => "west",
+        })
+    }
+}
+
+/// Parses `lon,lat`, or looks up a place in `places` by name (`Troy` or `Troy, AL`).
+pub fn locate(places: &[Place], input: &str) -> Result<Coordinate> {
+    if let Some((lon, lat)) = input
+        .split(',')
+        .map(|s| s.trim().parse::<f64>())
+        .collect_tuple()
+    {
+        if let (Ok(lon), Ok(lat)) = (lon, lat) {
+            return Ok(Coordinate { x: lon, y: lat });
+        }
+    }
+
+    let (name, state) = match input.rfind(',') {
+        Some(comma) => (input[..comma].trim(), Some(input[comma + 1..].trim())),
+        None => (input.trim(), None),
+    };
+    let matches = places
+        .iter()
+        .filter(|place| {
+            place.name.eq_ignore_ascii_case(name)
+                && match state {
+                    Some(state) => place.state.eq_ignore_ascii_case(state),
+                    None => true,
+                }
+        })
+        .collect::<Vec<_>>();
+    match matches.as_slice() {
+        [place] => Ok(place.location),
+        [] => Err(anyhow!("no place named {:?} in places.csv", input)),
+        _ => Err(anyhow!(
+            "{:?} is ambiguous: {}",
+            input,
+            matches
+                .iter()
+                .map(|place| format!("{}, {}", place.name, place.state))
+                .join("; ")
+        )),
+    }
+}
+
+/// The lines that pass within `within` of `point`, nearest first.
+pub fn query<'a>(fields: &[&'a Field], point: Coordinate, within: Length) -> Vec<Nearby<'a>> {
+    let mut nearby = fields
+        .iter()
+        .filter_map(|field| {
+            let (distance, side) = field
+                .line
+                .tuple_windows()
+                .map(|(start, end)| cross_track(Line { start, end }, point))
+                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())?;
+            let distance = Length::new::<meter>(distance);
+            if distance <= within {
+                Some(Nearby {
+                    field,
+                    distance,
+                    side,
+                })
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    nearby.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+    nearby
+}
+
+/// Distance in meters from `point` to the great circle segment `line`, and which side of it the
+/// point is on.
+pub(crate) fn cross_track(line: Line, point: Coordinate) -> (f64, Side) {
+    let (start, end, point) = (
+        Point::from(line.start),
+        Point::from(line.end),
+        Point::from(point),
+    );
+    let delta_13 = start.haversine_distance(&point) / EARTH_RADIUS;
+    let theta_12 = start.bearing(end).to_radians();
+    let theta_13 = start.bearing(point).to_radians();
+    let cross = (delta_13.sin() * (theta_13 - theta_12).sin()).asin();
+    let along = (delta_13.cos() / cross.cos()).min(1.0).acos();
+
+    // Cross-track distance is positive to the right of the line's direction.
+    let normal = theta_12.to_degrees() + if cross > 0.0 { 90.0 } else { -90.0 };
+    let side = match ((normal.rem_euclid(360.0) + 45.0) / 90.0) as u32 % 4 {
+        0 => Side::North,
+        1 => Side::East,
+        2 => Side::South,
+        _ => Side::West,
+    };
+
+    let distance = if (theta_13 - theta_12).cos() < 0.0 {
+        start.haversine_distance(&point)
+    } else if along > start.haversine_distance(&end) / EARTH_RADIUS {
+        end.haversine_distance(&point)
+    } else {
+        cross.abs() * EARTH_RADIUS
+    };
+    (distance, side)
+}
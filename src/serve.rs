@@ -0,0 +1,218 @@
+use anyhow::Result;
+use hex::FromHex;
+use std::fs;
+use std::io::{prelude::*, BufReader};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const EVENTS_PATH: &str = "/_navarro/events";
+
+/// A small HTTP server for a site directory that tells connected pages to reload (or show an error
+/// overlay) over server-sent events.
+#[derive(Clone)]
+pub struct Server {
+    dir: Arc<PathBuf>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    clients: Vec<TcpStream>,
+    error: Option<String>,
+}
+
+impl Server {
+    pub fn start(addr: impl ToSocketAddrs, dir: &Path) -> Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        eprintln!("serving site at http://{}/", listener.local_addr()?);
+
+        let server = Server {
+            dir: Arc::new(dir.to_owned()),
+            inner: Arc::default(),
+        };
+        let accept = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = accept.clone();
+                thread::spawn(move || {
+                    if let Err(err) = server.handle(stream) {
+                        eprintln!("serve: {:?}", err);
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn reload(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.error = None;
+        inner.broadcast("reload", "");
+    }
+
+    pub fn error(&self, error: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.error = Some(error.to_string());
+        inner.broadcast("build-error", error);
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        let mut range = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some(colon) = header.find(':') {
+                if header[..colon].eq_ignore_ascii_case("range") {
+                    range = Some(header[colon + 1..].trim().to_string());
+                }
+            }
+        }
+
+        let path = match request.split_whitespace().nth(1) {
+            Some(path) => path.split(&['?', '#'][..]).next().unwrap(),
+            None => return respond(stream, "400 Bad Request", "text/plain", b"bad request"),
+        };
+
+        if path == EVENTS_PATH {
+            let mut stream = stream;
+            stream.write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\r\n",
+            )?;
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(error) = &inner.error {
+                send(&stream, "build-error", error)?;
+            }
+            inner.clients.push(stream);
+            return Ok(());
+        }
+
+        let path = percent_decode(path);
+        let relative = Path::new(path.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return respond(stream, "403 Forbidden", "text/plain", b"forbidden");
+        }
+        let mut file = self.dir.join(relative);
+        if file.is_dir() {
+            file = file.join("index.html");
+        }
+
+        let body = match fs::read(&file) {
+            Ok(body) => body,
+            Err(_) => return respond(stream, "404 Not Found", "text/plain", b"not found"),
+        };
+        let content_type = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => "text/html; charset=utf-8",
+            Some("css") => "text/css",
+            Some("js") => "application/javascript",
+            Some("kml") => "application/vnd.google-earth.kml+xml",
+            Some("kmz") => "application/vnd.google-earth.kmz",
+            Some("geojson") => "application/geo+json",
+            Some("png") => "image/png",
+            Some("pbf") => "application/x-protobuf",
+            _ => "application/octet-stream",
+        };
+        if content_type.starts_with("text/html") {
+            let html = String::from_utf8_lossy(&body).replace(
+                "</body>",
+                concat!(
+                    "<script>",
+                    include_str!("../templates/reload.js"),
+                    "</script></body>"
+                ),
+            );
+            respond(stream, "200 OK", content_type, html.as_bytes())
+        } else if let Some((start, end)) = range.and_then(|range| byte_range(&range, body.len())) {
+            // PMTiles readers fetch archives a piece at a time.
+            let status = format!(
+                "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                start,
+                end,
+                body.len()
+            );
+            respond(stream, &status, content_type, &body[start..=end])
+        } else {
+            respond(stream, "200 OK", content_type, &body)
+        }
+    }
+}
+
+impl Inner {
+    fn broadcast(&mut self, event: &str, data: &str) {
+        self.clients
+            .retain(|stream| send(stream, event, data).is_ok());
+    }
+}
+
+fn send(mut stream: &TcpStream, event: &str, data: &str) -> std::io::Result<()> {
+    let mut message = format!("event: {}\n", event);
+    for line in data.lines() {
+        message.push_str(&format!("data: {}\n", line));
+    }
+    message.push('\n');
+    stream.write_all(message.as_bytes())
+}
+
+fn respond(mut stream: TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+/// Parses a single `bytes=start-end` range, clamped to `len`.
+fn byte_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let range = range.strip_prefix("bytes=")?;
+    let dash = range.find('-')?;
+    let (start, end) = (&range[..dash], &range[dash + 1..]);
+    let (start, end) = match (start.parse::<usize>().ok(), end.parse::<usize>().ok()) {
+        (Some(start), Some(end)) => (start, end.min(len.checked_sub(1)?)),
+        (Some(start), None) => (start, len.checked_sub(1)?),
+        (None, Some(suffix)) => (len.saturating_sub(suffix), len.checked_sub(1)?),
+        (None, None) => return None,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let input = s.as_bytes();
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input.get(i + 1..i + 3).map(<[u8; 1]>::from_hex) {
+            Some(Ok([decoded])) if input[i] == b'%' => {
+                bytes.push(decoded);
+                i += 3;
+            }
+            _ => {
+                bytes.push(input[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,497 @@
+use crate::gazetteer::{Gazetteer, Place, StateBoundary};
+use crate::geo::*;
+use crate::output::{OutputWriter, Site};
+use crate::story::Story;
+use crate::survey::{self, Survey};
+use crate::template::*;
+use crate::{image, timing, Boundary, LatLonBox, Team};
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{prelude::*, BufReader, ErrorKind};
+#[cfg(feature = "hotwatch")]
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use uom::si::f64::Length;
+use uom::si::length::{foot, meter};
+
+/// Everything the outputs are made from, kept around so that watch mode can update only what
+/// depends on the file that changed.
+#[derive(Clone)]
+pub struct State {
+    root: PathBuf,
+    outputs: Vec<Arc<dyn OutputWriter>>,
+    revision: String,
+    timestamp: i64,
+    data: Data,
+    story: Vec<Story>,
+    teams: Vec<Team>,
+    surveys: HashMap<String, Survey>,
+    fields: HashMap<String, Field>,
+    images: BTreeMap<String, Vec<u8>>,
+}
+
+/// What a field is computed against, besides its survey: `boundary.kml`, `places.csv` and the
+/// optional `states.kml` in `data/`.
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub boundary: Boundary,
+    pub places: Vec<Place>,
+    pub states: Vec<StateBoundary>,
+}
+
+impl Data {
+    /// Loads the files in `root/data`.
+    pub fn load(root: &Path) -> Result<Data> {
+        Ok(Data {
+            boundary: load_boundary(root)?,
+            places: load_places(root)?,
+            states: load_states(root)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Survey(String),
+    Teams,
+    Boundary,
+    Places,
+    States,
+    Story,
+    /// Events may have been missed, so nothing can be assumed to be unchanged.
+    Rescan,
+    /// Templates and fonts are compiled into navarro, so changes to them need a restart.
+    Rebuild(PathBuf),
+}
+
+#[cfg(feature = "hotwatch")]
+impl Change {
+    pub fn from_path(path: &Path) -> Option<Change> {
+        if path.extension()? == "kml" && path.parent()?.file_name()? == "survey" {
+            Some(Change::Survey(path.file_stem()?.to_str()?.to_string()))
+        } else if path.file_name()? == "teams.csv" {
+            Some(Change::Teams)
+        } else if path.file_name()? == "boundary.kml" {
+            Some(Change::Boundary)
+        } else if path.file_name()? == "places.csv" {
+            Some(Change::Places)
+        } else if path.file_name()? == "states.kml" {
+            Some(Change::States)
+        } else if path.file_name()? == "story.csv" {
+            Some(Change::Story)
+        } else if path.parent()?.file_name()? == "templates" || path.extension()? == "ttf" {
+            Some(Change::Rebuild(path.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+impl State {
+    /// Loads everything under `root` (the directory holding `data/`, `survey/` and `site/`) to
+    /// write `outputs` into `root/site`.
+    pub fn load(root: &Path, outputs: Vec<Arc<dyn OutputWriter>>) -> Result<State> {
+        let mut state = State {
+            root: root.to_owned(),
+            outputs,
+            revision: revision(root)?,
+            timestamp: timestamp(root)?,
+            data: Data::load(root)?,
+            story: load_story(root)?,
+            teams: Vec::new(),
+            surveys: HashMap::new(),
+            fields: HashMap::new(),
+            images: BTreeMap::new(),
+        };
+        state.update(&Change::Teams)?;
+        Ok(state)
+    }
+
+    /// Starts with nothing loaded, for watch mode to load everything into with `try_update`, so that
+    /// a file that's broken at startup is left out until it's fixed rather than ending the session.
+    #[cfg(feature = "hotwatch")]
+    pub fn empty(root: &Path, outputs: Vec<Arc<dyn OutputWriter>>) -> Result<State> {
+        Ok(State {
+            root: root.to_owned(),
+            outputs,
+            revision: revision(root)?,
+            timestamp: timestamp(root)?,
+            data: Data {
+                boundary: Boundary(LineString::from(Vec::<Coordinate>::new())),
+                places: Vec::new(),
+                states: Vec::new(),
+            },
+            story: Vec::new(),
+            teams: Vec::new(),
+            surveys: HashMap::new(),
+            fields: HashMap::new(),
+            images: BTreeMap::new(),
+        })
+    }
+
+    /// Applies each of `changes` on its own and writes the outputs if any of them worked. A change
+    /// that fails (including with a panic from a malformed survey) leaves the state as it was, and
+    /// is returned along with why so that it can be retried.
+    #[cfg(feature = "hotwatch")]
+    pub fn try_update(&mut self, changes: &[Change]) -> Result<Vec<(Change, anyhow::Error)>> {
+        let mut failed = Vec::new();
+        for change in changes {
+            let mut next = self.clone();
+            match catch_panic(|| next.update(change)) {
+                Ok(()) => *self = next,
+                Err(err) => failed.push((change.clone(), err)),
+            }
+        }
+        if failed.len() < changes.len() {
+            catch_panic(|| self.write())?;
+        }
+        Ok(failed)
+    }
+
+    pub fn update(&mut self, change: &Change) -> Result<()> {
+        match change {
+            Change::Survey(name) => {
+                if let Some(team) = self.teams.iter().find(|team| &team.name == name).cloned() {
+                    let survey = load_survey(&self.root, &team, &self.data)?;
+                    self.set_survey(&team, survey);
+                }
+            }
+            Change::Teams => {
+                let teams = load_teams(&self.root)?;
+                let names = teams.iter().map(|team| &team.name).collect::<HashSet<_>>();
+                self.surveys.retain(|name, _| names.contains(name));
+                self.fields.retain(|name, _| names.contains(name));
+
+                let surveys = teams
+                    .par_iter()
+                    .map(|team| -> Result<_> {
+                        if self.teams.iter().any(|old| old.name == team.name) {
+                            Ok(None)
+                        } else {
+                            Ok(Some(load_survey(&self.root, team, &self.data)?))
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                for (team, survey) in teams.iter().zip(surveys) {
+                    if let Some(survey) = survey {
+                        self.set_survey(team, survey);
+                    } else if let Some(field) = self.fields.get_mut(&team.name) {
+                        field.team = team.clone();
+                    }
+                }
+
+                // Drop the images of teams that were removed or changed, to render them again when
+                // they're next needed.
+                let filenames = teams
+                    .iter()
+                    .filter(|team| self.teams.contains(team))
+                    .map(label_filename)
+                    .chain(teams.iter().map(field_filename))
+                    .collect::<HashSet<_>>();
+                self.images = std::mem::take(&mut self.images)
+                    .into_iter()
+                    .filter(|(filename, _)| filenames.contains(filename))
+                    .collect();
+                self.teams = teams;
+            }
+            Change::Places => {
+                self.data.places = load_places(&self.root)?;
+                self.annotate();
+            }
+            Change::States => {
+                self.data.states = load_states(&self.root)?;
+                self.annotate();
+            }
+            Change::Story => self.story = load_story(&self.root)?,
+            Change::Rescan => *self = State::load(&self.root, self.outputs.clone())?,
+            Change::Rebuild(_) => {}
+            Change::Boundary => {
+                self.data.boundary = load_boundary(&self.root)?;
+                let data = &self.data;
+                let surveys = &self.surveys;
+                self.fields = self
+                    .teams
+                    .par_iter()
+                    .filter_map(|team| {
+                        let survey = surveys.get(&team.name)?;
+                        Some(
+                            field(team, survey, data)
+                                .map(|field| (team.name.clone(), field))
+                                .ok_or_else(|| {
+                                    anyhow!("{}'s line doesn't cross the boundary", team.name)
+                                }),
+                        )
+                    })
+                    .collect::<Result<_>>()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn annotate(&mut self) {
+        let data = &self.data;
+        self.fields.par_iter_mut().for_each(|(_, field)| {
+            field.gazetteer = Gazetteer::new(&field.line.collect_vec(), &data.places, &data.states)
+        });
+    }
+
+    /// Renders and encodes (in parallel) any images that are missing. They're only needed to write
+    /// outputs, so they're made then rather than when teams load. Field images only depend on the
+    /// color, so teams share them and each is only rendered once.
+    fn add_images(&mut self) -> Result<()> {
+        let mut jobs = Vec::new();
+        for team in &self.teams {
+            let label_filename = label_filename(team);
+            if !self.images.contains_key(&label_filename) {
+                jobs.push((label_filename, team, true));
+            }
+            let field_filename = field_filename(team);
+            if !self.images.contains_key(&field_filename)
+                && !jobs
+                    .iter()
+                    .any(|(filename, _, _)| filename == &field_filename)
+            {
+                jobs.push((field_filename, team, false));
+            }
+        }
+        let images = jobs
+            .into_par_iter()
+            .map(|(filename, team, label)| -> Result<_> {
+                let pixmap = if label {
+                    image::label(team)?
+                } else {
+                    image::field(team)
+                };
+                Ok((filename, image::encode(&pixmap)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.images.extend(images);
+        Ok(())
+    }
+
+    fn set_survey(&mut self, team: &Team, survey: Option<(Survey, Field)>) {
+        if let Some((survey, field)) = survey {
+            self.surveys.insert(team.name.clone(), survey);
+            self.fields.insert(team.name.clone(), field);
+        } else {
+            self.surveys.remove(&team.name);
+            self.fields.remove(&team.name);
+        }
+    }
+
+    /// Every team's field, in `teams.csv` order.
+    pub fn fields(&self) -> Vec<&Field> {
+        self.teams
+            .iter()
+            .filter_map(|team| self.fields.get(&team.name))
+            .collect()
+    }
+
+    pub fn places(&self) -> &[Place] {
+        &self.data.places
+    }
+
+    /// Writes every output from the whole state. Outputs aren't patched in place; they're single
+    /// documents and archives, and rendering them is cheap next to the surveying and image encoding
+    /// that `update` limits to what changed.
+    pub fn write(&mut self) -> Result<()> {
+        timing::time("images", || self.add_images())?;
+        let fields = self.fields();
+
+        let site_dir = self.root.join("site");
+        fs::create_dir_all(&site_dir)?;
+
+        let site = Site {
+            revision: &self.revision,
+            timestamp: self.timestamp,
+            boundary: &self.data.boundary,
+            places: &self.data.places,
+            states: &self.data.states,
+            story: &self.story,
+            teams: &self.teams,
+            fields: &fields,
+            images: &self.images,
+        };
+        for output in &self.outputs {
+            timing::time(output.name(), || output.write(&site, &site_dir))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hotwatch")]
+fn catch_panic(f: impl FnOnce() -> Result<()>) -> Result<()> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        anyhow!(
+            "panicked: {}",
+            payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("(unknown)")
+        )
+    })?
+}
+
+fn revision(root: &Path) -> Result<String> {
+    let revision = match option_env!("COMMIT_REF") {
+        Some(rev) => Cow::from(rev),
+        None => String::from_utf8(
+            Command::new("git")
+                .current_dir(root)
+                .args(&["rev-parse", "HEAD"])
+                .output()?
+                .stdout,
+        )?
+        .into(),
+    };
+    Ok(revision.trim().to_string())
+}
+
+/// Seconds since the epoch to stamp output files with: `SOURCE_DATE_EPOCH` if set, otherwise the
+/// commit time of `HEAD`, otherwise (outside of a git checkout) the start of 1980, the earliest time
+/// a ZIP file can hold.
+fn timestamp(root: &Path) -> Result<i64> {
+    if let Ok(timestamp) = std::env::var("SOURCE_DATE_EPOCH") {
+        return Ok(timestamp.trim().parse()?);
+    }
+    match Command::new("git")
+        .current_dir(root)
+        .args(&["log", "-1", "--format=%ct"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8(output.stdout)?.trim().parse()?)
+        }
+        _ => Ok(315_532_800),
+    }
+}
+
+fn load_boundary(root: &Path) -> Result<Boundary> {
+    Ok(Boundary::load(BufReader::new(File::open(
+        root.join("data").join("boundary.kml"),
+    )?)))
+}
+
+fn load_teams(root: &Path) -> Result<Vec<Team>> {
+    BufReader::new(File::open(root.join("data").join("teams.csv"))?)
+        .lines()
+        .skip(1)
+        .map(|line| line?.parse())
+        .collect()
+}
+
+fn load_places(root: &Path) -> Result<Vec<Place>> {
+    BufReader::new(File::open(root.join("data").join("places.csv"))?)
+        .lines()
+        .skip(1)
+        .map(|line| line?.parse())
+        .collect()
+}
+
+fn load_story(root: &Path) -> Result<Vec<Story>> {
+    BufReader::new(File::open(root.join("data").join("story.csv"))?)
+        .lines()
+        .skip(1)
+        .map(|line| line?.parse())
+        .collect()
+}
+
+/// State outlines are optional; without them, the gazetteer guesses states from the nearest
+/// place in `places.csv`.
+fn load_states(root: &Path) -> Result<Vec<StateBoundary>> {
+    match fs::read_to_string(root.join("data").join("states.kml")) {
+        Ok(kml) => Ok(StateBoundary::load(&kml)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_survey(root: &Path, team: &Team, data: &Data) -> Result<Option<(Survey, Field)>> {
+    let kml = match fs::read_to_string(root.join("survey").join(&team.name).with_extension("kml")) {
+        Ok(x) => x,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let survey = timing::time("survey", || survey::default(&kml));
+    let field = field(team, &survey, data)
+        .ok_or_else(|| anyhow!("{}'s line doesn't cross the boundary", team.name))?;
+    Ok(Some((survey, field)))
+}
+
+fn label_filename(team: &Team) -> String {
+    format!("{}.png", team.name)
+}
+
+fn field_filename(team: &Team) -> String {
+    format!("{}.png", hex::encode(team.color))
+}
+
+/// Computes everything needed to draw a team's field, or `None` if the line through the field
+/// doesn't reach the boundary on both sides.
+pub fn field(team: &Team, survey: &Survey, data: &Data) -> Option<Field> {
+    let line = timing::time("limit", || data.boundary.limit(survey))?;
+    let center = (line.start + line.end) / 2.0;
+
+    let field_length = line
+        .interpolate()
+        .tuple_windows()
+        .map(|(start, end)| Line { start, end }.haversine_length())
+        .sum::<f64>();
+
+    lazy_static! {
+        static ref FIELD_WIDTH: Length = Length::new::<foot>(160.0);
+        static ref LABEL_WIDTH: Length = *FIELD_WIDTH * 500.0;
+        static ref LABEL_HEIGHT: Length = Length::new::<foot>(360.0) * 500.0;
+        static ref LABEL_DIAGONAL: Length = ((*LABEL_HEIGHT).powi(uom::typenum::P2::new())
+            + (*LABEL_WIDTH).powi(uom::typenum::P2::new()))
+        .sqrt();
+    }
+
+    // Each level is simplified to within a pixel at the largest size it's drawn at.
+    let full = line.interpolate().collect::<Vec<_>>();
+    let mut line_lods: Vec<LineLod> = Vec::new();
+    for &max_pixels in &[1024, 4096, 16384, -1] {
+        let line = if max_pixels > 0 {
+            simplify_tessellated(&full, field_length / f64::from(max_pixels))
+        } else {
+            full.clone()
+        };
+        match line_lods.last_mut() {
+            Some(lod) if lod.line == line => lod.max_pixels = max_pixels,
+            _ => line_lods.push(LineLod {
+                min_pixels: line_lods.last().map_or(0, |lod| lod.max_pixels),
+                max_pixels,
+                line,
+            }),
+        }
+    }
+
+    Some(Field {
+        team: team.clone(),
+        stadium: survey.field,
+        field: LatLonBox::new(center, *FIELD_WIDTH, Length::new::<meter>(field_length))
+            .adjust_width(survey.field, *FIELD_WIDTH),
+        field_bearing: center.bearing_from_slope(line.slope()),
+        line: line.interpolate(),
+        line_region: LatLonBox::new(
+            center,
+            Length::new::<meter>(field_length),
+            Length::new::<meter>(field_length),
+        ),
+        line_lods,
+        label: LatLonBox::new(survey.field, *LABEL_WIDTH, *LABEL_HEIGHT),
+        label_bearing: survey.bearing,
+        label_region: LatLonBox::new(survey.field, *LABEL_DIAGONAL, *LABEL_DIAGONAL),
+        gazetteer: timing::time("gazetteer", || {
+            Gazetteer::new(&full, &data.places, &data.states)
+        }),
+    })
+}
@@ -0,0 +1,102 @@
+use crate::geo::*;
+use crate::intersections::intersections;
+use crate::template::Field;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// Something that happens in _20020_, from `data/story.csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Story {
+    pub chapter: u32,
+    /// Names of the teams involved.
+    pub teams: Vec<String>,
+    /// Where it happens, if not at the teams' stadium or where their lines cross.
+    pub location: Option<Coordinate>,
+    pub description: String,
+}
+
+/// Parses a row of `data/story.csv`. Teams are separated by `+`. The description (the last column)
+/// can contain commas, and can be quoted the way spreadsheets export it: wrapped in `"`, with `""`
+/// for a literal quote. The other columns are never quoted.
+impl FromStr for Story {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Story> {
+        let mut iter = s.splitn(5, ',');
+        let mut next = || {
+            iter.next()
+                .ok_or_else(|| anyhow!("not enough columns: {:?}", s))
+        };
+        let chapter = next()?.parse()?;
+        let teams = next()?
+            .split('+')
+            .map(|team| team.trim().to_string())
+            .collect();
+        let location = match (next()?, next()?) {
+            ("", "") => None,
+            (lat, lon) => Some(Coordinate {
+                x: lon.parse()?,
+                y: lat.parse()?,
+            }),
+        };
+        Ok(Story {
+            chapter,
+            teams,
+            location,
+            description: unquote(next()?.trim())?,
+        })
+    }
+}
+
+fn unquote(s: &str) -> Result<String> {
+    let quoted = match s.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => return Ok(s.to_string()),
+    };
+    match quoted.strip_suffix('"') {
+        Some(inner) if !inner.replace("\"\"", "").contains('"') => Ok(inner.replace("\"\"", "\"")),
+        _ => Err(anyhow!("badly quoted description: {:?}", s)),
+    }
+}
+
+/// A row of `story.csv`, placed on the map.
+#[derive(Debug, Clone)]
+pub struct Event<'a> {
+    /// For linking to the event's placemark.
+    pub id: String,
+    pub story: &'a Story,
+    pub fields: Vec<&'a Field>,
+    pub location: Coordinate,
+}
+
+/// Places each event at its own location if it has one, otherwise where the teams' lines cross,
+/// otherwise at the first team's stadium. Events with none of those (because no team has a field
+/// yet) are left out.
+pub fn events<'a>(story: &'a [Story], fields: &[&'a Field]) -> Vec<Event<'a>> {
+    story
+        .iter()
+        .enumerate()
+        .filter_map(|(i, story)| {
+            let fields = story
+                .teams
+                .iter()
+                .filter_map(|team| fields.iter().find(|field| &field.team.name == team))
+                .copied()
+                .collect::<Vec<_>>();
+            let location = story
+                .location
+                .or_else(|| {
+                    intersections(&fields, &[])
+                        .first()
+                        .map(|intersection| intersection.location)
+                })
+                .or_else(|| fields.first().map(|field| field.stadium))?;
+            Some(Event {
+                id: format!("story-{}", i + 1),
+                story,
+                fields,
+                location,
+            })
+        })
+        .collect()
+}
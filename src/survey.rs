@@ -1,9 +1,12 @@
 use crate::geo::*;
 use crate::ord::OrdF64;
+use anyhow::{anyhow, Result};
 use derive_more::{Add, Sum};
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Survey {
@@ -29,10 +32,11 @@ impl Survey {
     }
 }
 
+/// Surveys a KML file with whichever method below fits it.
 pub fn default(kml: &str) -> Survey {
     macro_rules! team {
         ($name:expr) => {
-            kml.contains(concat!("<name>", $name, "</name>"));
+            kml.contains(concat!("<name>", $name, "</name>"))
         };
     }
 
@@ -47,13 +51,74 @@ pub fn default(kml: &str) -> Survey {
     }
 }
 
+/// The survey methods below, for surveying a file with a particular one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    HashMark,
+    SidelinesAnd50,
+    Stanford,
+    Syracuse,
+}
+
+impl Method {
+    pub const ALL: [Method; 4] = [
+        Method::HashMark,
+        Method::SidelinesAnd50,
+        Method::Stanford,
+        Method::Syracuse,
+    ];
+
+    pub fn survey(self, kml: &str) -> Survey {
+        match self {
+            Method::HashMark => hash_mark(kml),
+            Method::SidelinesAnd50 => sidelines_and_50(kml),
+            Method::Stanford => stanford(kml),
+            Method::Syracuse => syracuse(kml),
+        }
+    }
+
+    /// Whether `kml` has the lines and placemarks this method expects.
+    pub fn fits(self, kml: &str) -> bool {
+        let (marks, lines) = (placemarks(kml).count(), lines(kml).count());
+        match self {
+            Method::HashMark => marks >= 10,
+            Method::SidelinesAnd50 => lines == 3,
+            Method::Stanford => lines > 0,
+            Method::Syracuse => marks >= 2 && lines == 2,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Method::HashMark => "hash_mark",
+            Method::SidelinesAnd50 => "sidelines_and_50",
+            Method::Stanford => "stanford",
+            Method::Syracuse => "syracuse",
+        })
+    }
+}
+
+impl FromStr for Method {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Method> {
+        Method::ALL
+            .iter()
+            .copied()
+            .find(|method| method.to_string() == s)
+            .ok_or_else(|| anyhow!("unknown survey method {:?}", s))
+    }
+}
+
 /// Expects a KML file of 3 lines and any number of placemarks. The first line is expected to be
 /// the 50 yard line. The next 2 lines are expected to be the sidelines.
 ///
 /// Calculates the field location as the center of the 50 yard line's intersection with the
 /// sidelines. Calculates the heading as the linear regression of the sideline points and any
 /// additional placemarks.
-fn sidelines_and_50(kml: &str) -> Survey {
+pub fn sidelines_and_50(kml: &str) -> Survey {
     let mut lines = lines(kml);
     let fifty = lines.next().unwrap();
     let sidelines = lines.collect_tuple::<(_, _)>().unwrap();
@@ -99,7 +164,7 @@ fn sidelines_and_50_inner(
 /// placemarks, the heading is the average of both the parallel and perpendicular lines between the
 /// placemarks. If there are more than 10, the heading is taken as a linear regression of all
 /// placemarks.
-fn hash_mark(kml: &str) -> Survey {
+pub fn hash_mark(kml: &str) -> Survey {
     let marks = placemarks(kml).collect::<Vec<_>>();
     let field = coord_average(marks.iter().copied().take(10));
 
@@ -131,7 +196,13 @@ fn hash_mark(kml: &str) -> Survey {
     Survey::from_slope(field, slope)
 }
 
-fn stanford(kml: &str) -> Survey {
+/// Expects a KML file of any number of lines and placemarks, for fields where the lines can't be
+/// drawn to the usual landmarks.
+///
+/// Calculates the field location as the average of the line endpoints. Calculates the heading as
+/// the linear regression of the placemarks and the field location if there are any placemarks,
+/// otherwise as the average slope of the lines.
+pub fn stanford(kml: &str) -> Survey {
     let lines = lines(kml).collect::<Vec<_>>();
     let field = coord_average(
         lines
@@ -150,8 +221,8 @@ fn stanford(kml: &str) -> Survey {
     Survey::from_slope(field, slope)
 }
 
-// First two placemarks create the 50 yard line; first two lines are sidelines. Sorta :)
-fn syracuse(kml: &str) -> Survey {
+/// First two placemarks create the 50 yard line; first two lines are sidelines. Sorta :)
+pub fn syracuse(kml: &str) -> Survey {
     let mut points = placemarks(kml);
     let fifty = Line {
         start: points.next().unwrap(),
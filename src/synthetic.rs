@@ -0,0 +1,110 @@
+use crate::geo::*;
+use crate::survey::Method;
+use crate::template::Synthetic;
+use anyhow::Result;
+use askama::Template;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use uom::si::f64::Length;
+use uom::si::length::{foot, meter, yard};
+
+/// Writes a survey KML for a field centered on `field` whose sidelines run along `bearing`, laid
+/// out the way `method` expects, with every point moved by Gaussian noise of standard deviation
+/// `noise` (in each of x and y).
+pub fn generate(
+    field: Coordinate,
+    bearing: f64,
+    method: Method,
+    noise: Length,
+    rng: &mut impl Rng,
+) -> Result<String> {
+    let normal = Normal::new(0.0, noise.get::<meter>())?;
+    // A point `along` yards from the 50 yard line towards `bearing` and `across` yards to the right
+    // of the middle of the field.
+    let mut point = |along: f64, across: f64| -> Coordinate {
+        let center = Point::from(field);
+        let point = center
+            .haversine_destination(bearing, Length::new::<yard>(along).get::<meter>())
+            .haversine_destination(bearing + 90.0, Length::new::<yard>(across).get::<meter>());
+        let (east, north) = (normal.sample(rng), normal.sample(rng));
+        point
+            .haversine_destination(90.0, east)
+            .haversine_destination(0.0, north)
+            .into()
+    };
+    let sideline = Length::new::<foot>(80.0).get::<yard>();
+    // College hash marks are 60 feet in from each sideline.
+    let hash = Length::new::<foot>(20.0).get::<yard>();
+
+    let (placemarks, lines) = match method {
+        Method::HashMark => (
+            [-40.0, -20.0, 0.0, 20.0, 40.0]
+                .iter()
+                .flat_map(|&along| vec![point(along, -hash), point(along, hash)])
+                .collect(),
+            Vec::new(),
+        ),
+        Method::SidelinesAnd50 => (
+            Vec::new(),
+            vec![
+                Line::new(point(0.0, -sideline), point(0.0, sideline)),
+                Line::new(point(-50.0, -sideline), point(50.0, -sideline)),
+                Line::new(point(-50.0, sideline), point(50.0, sideline)),
+            ],
+        ),
+        // The sidelines between the 20s, as if the rest of the field were hidden.
+        Method::Stanford => (
+            Vec::new(),
+            vec![
+                Line::new(point(-30.0, -sideline), point(30.0, -sideline)),
+                Line::new(point(-30.0, sideline), point(30.0, sideline)),
+            ],
+        ),
+        Method::Syracuse => (
+            vec![point(0.0, -sideline), point(0.0, sideline)],
+            vec![
+                Line::new(point(-50.0, -sideline), point(50.0, -sideline)),
+                Line::new(point(-50.0, sideline), point(50.0, sideline)),
+            ],
+        ),
+    };
+
+    Ok(Synthetic {
+        name: &format!("Synthetic {} survey", method),
+        description: &format!(
+            "Generated by navarro: field at {:.7},{:.7}, bearing {:.3}°, noise {} m",
+            field.x,
+            field.y,
+            bearing,
+            noise.get::<meter>()
+        ),
+        placemarks,
+        lines,
+    }
+    .render()?)
+}
+
+/// How far off `method` is for a field at `field` and `bearing`, over `trials` generated surveys:
+/// the root mean square of the field location error and of the bearing error (in degrees, for the
+/// axis of the field, so 180° off is no error at all).
+pub fn accuracy(
+    field: Coordinate,
+    bearing: f64,
+    method: Method,
+    noise: Length,
+    trials: usize,
+    rng: &mut impl Rng,
+) -> Result<(Length, f64)> {
+    let (mut distance, mut angle) = (0.0, 0.0);
+    for _ in 0..trials {
+        let survey = method.survey(&generate(field, bearing, method, noise, rng)?);
+        distance += Point::from(field)
+            .haversine_distance(&Point::from(survey.field))
+            .powi(2);
+        angle += ((survey.bearing - bearing + 90.0).rem_euclid(180.0) - 90.0).powi(2);
+    }
+    Ok((
+        Length::new::<meter>((distance / trials as f64).sqrt()),
+        (angle / trials as f64).sqrt(),
+    ))
+}
@@ -1,31 +1,206 @@
-use crate::geo::Interpolate;
+use crate::compare::Comparison;
+use crate::gazetteer::{Gazetteer, StateBoundary};
+use crate::geo::{Coordinate, Interpolate};
+use crate::intersections::Intersection;
+use crate::story::Event;
+use crate::survey::Method;
+use crate::territory::Territory;
 use crate::{LatLonBox, Team};
 use askama::Template;
 
 #[derive(Debug, Template)]
 #[template(path = "20020.kml", escape = "xml")]
-pub(crate) struct Output<'a> {
+pub struct Output<'a> {
     pub kmz: bool,
+    /// Leaves out the description and view, for documents loaded through a `<NetworkLink>`.
+    pub linked: bool,
     pub revision: &'a str,
-    pub fields: &'a [Field],
+    pub states: &'a [StateBoundary],
+    pub story: &'a [Event<'a>],
+    pub fields: &'a [&'a Field],
 }
 
-#[derive(Debug)]
-pub(crate) struct Field {
+impl Output<'_> {
+    /// Events involving `field`'s team, to link to from its description.
+    fn events(&self, field: &Field) -> Vec<&Event<'_>> {
+        self.story
+            .iter()
+            .filter(|event| event.story.teams.contains(&field.team.name))
+            .collect()
+    }
+
+    /// The first chapter `field`'s team shows up in, if they're in the story.
+    fn chapter(&self, field: &Field) -> Option<u32> {
+        self.events(field)
+            .iter()
+            .map(|event| event.story.chapter)
+            .min()
+    }
+}
+
+#[derive(Debug, Template)]
+#[template(path = "split.kml", escape = "xml")]
+pub struct Split<'a> {
+    pub revision: &'a str,
+    pub fields: &'a [&'a Field],
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
     pub team: Team,
+    pub stadium: Coordinate,
     pub field: LatLonBox,
     pub field_bearing: f64,
     pub line: Interpolate,
+    /// A square around the line, for line `<Region>`s; its size on screen is about the line's.
+    pub line_region: LatLonBox,
+    pub line_lods: Vec<LineLod>,
     pub label: LatLonBox,
     pub label_bearing: f64,
     pub label_region: LatLonBox,
+    pub gazetteer: Gazetteer,
+}
+
+/// The line simplified for when `line_region` is between `min_pixels` and `max_pixels` (-1 for no
+/// limit) across on screen.
+#[derive(Debug, Clone)]
+pub struct LineLod {
+    pub min_pixels: i32,
+    pub max_pixels: i32,
+    pub line: Vec<Coordinate>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "20020.geojson", escape = "none")]
+pub struct GeoJson<'a> {
+    pub revision: &'a str,
+    pub fields: &'a [&'a Field],
+}
+
+#[derive(Debug, Template)]
+#[template(path = "poster.svg", escape = "xml")]
+pub struct Poster<'a> {
+    pub revision: &'a str,
+    pub width: f64,
+    pub height: f64,
+    /// SVG `points` for the boundary.
+    pub boundary: String,
+    pub fields: Vec<PosterField<'a>>,
+}
+
+#[derive(Debug)]
+pub struct PosterField<'a> {
+    pub team: &'a Team,
+    /// SVG `points` for the line.
+    pub line: String,
+    pub stadium: Coordinate,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "compare.kml", escape = "xml")]
+pub struct Compare<'a> {
+    pub files: Vec<ComparedFile<'a>>,
+}
+
+#[derive(Debug)]
+pub struct ComparedFile<'a> {
+    pub name: &'a str,
+    pub comparison: &'a Comparison,
+}
+
+impl ComparedFile<'_> {
+    fn is_default(&self, i: &usize) -> bool {
+        self.comparison.default == Some(*i)
+    }
+
+    fn is_recommended(&self, i: &usize) -> bool {
+        self.comparison.recommended() == Some(*i)
+    }
+}
+
+impl Compare<'_> {
+    fn color(&self, method: &Method) -> [u8; 3] {
+        match method {
+            Method::HashMark => [0xe4, 0x1a, 0x1c],
+            Method::SidelinesAnd50 => [0x37, 0x7e, 0xb8],
+            Method::Stanford => [0x4d, 0xaf, 0x4a],
+            Method::Syracuse => [0x98, 0x4e, 0xa3],
+        }
+    }
+}
+
+#[derive(Debug, Template)]
+#[template(path = "diff.kml", escape = "xml")]
+pub struct Diff<'a> {
+    pub name: &'a str,
+    pub versions: [DiffVersion<'a>; 2],
+}
+
+#[derive(Debug)]
+pub struct DiffVersion<'a> {
+    pub label: &'a str,
+    pub color: [u8; 3],
+    pub field: Coordinate,
+    /// Empty if the line doesn't reach the boundary.
+    pub line: Vec<Coordinate>,
+}
+
+#[cfg(feature = "synthetic")]
+#[derive(Debug, Template)]
+#[template(path = "synthetic.kml", escape = "xml")]
+pub struct Synthetic<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub placemarks: Vec<Coordinate>,
+    pub lines: Vec<crate::geo::Line>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "intersections.kml", escape = "xml")]
+pub struct Intersections<'a> {
+    pub revision: &'a str,
+    pub intersections: &'a [Intersection<'a>],
+}
+
+#[derive(Debug, Template)]
+#[template(path = "territories.kml", escape = "xml")]
+pub struct Territories<'a> {
+    pub revision: &'a str,
+    pub territories: &'a [Territory<'a>],
+}
+
+#[derive(Debug, Template)]
+#[template(path = "territories.geojson", escape = "none")]
+pub struct TerritoriesGeoJson<'a> {
+    pub revision: &'a str,
+    pub territories: &'a [Territory<'a>],
+}
+
+#[derive(Debug, Template)]
+#[template(path = "index.html", escape = "html")]
+pub struct Index<'a> {
+    pub revision: &'a str,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "leaflet.html", escape = "html")]
+pub struct Leaflet<'a> {
+    pub revision: &'a str,
+    pub teams: &'a [Team],
+    pub fields: &'a [&'a Field],
+}
+
+impl Leaflet<'_> {
+    fn surveyed(&self, team: &Team) -> bool {
+        self.fields.iter().any(|field| field.team.name == team.name)
+    }
 }
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 #[derive(Debug, Template)]
 #[template(path = "label.svg", escape = "xml")]
-pub(crate) struct Label<'a> {
+pub struct Label<'a> {
     pub team: &'a Team,
     pub contrast_color: &'static str,
 }
@@ -34,12 +209,75 @@ pub(crate) struct Label<'a> {
 
 mod filters {
     use askama::Result;
+    use uom::si::area::square_mile;
+    use uom::si::f64::{Area, Length};
+    use uom::si::length::{meter, mile};
+
+    /// Chapters as KML years (chapter 2 is the year 0002), so that the time slider steps through
+    /// the story.
+    pub(super) fn chapter(chapter: &u32) -> Result<String> {
+        Ok(format!("{:04}", chapter))
+    }
 
     pub(super) fn css_color(color: &[u8; 3]) -> Result<String> {
         Ok(hex::encode(color))
     }
 
+    /// Fixed-precision (roughly centimeter) floats, so that output doesn't churn on noise in the
+    /// last few bits.
+    pub(super) fn fixed(n: &f64) -> Result<String> {
+        let s = format!("{:.7}", n);
+        Ok(match s.strip_prefix('-') {
+            Some(abs) if abs.bytes().all(|b| b == b'0' || b == b'.') => abs.to_string(),
+            _ => s,
+        })
+    }
+
+    pub(super) fn json_str(s: &str) -> Result<String> {
+        let mut json = String::with_capacity(s.len() + 2);
+        json.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+                c => json.push(c),
+            }
+        }
+        json.push('"');
+        Ok(json)
+    }
+
     pub(super) fn kml_color(color: &[u8; 3]) -> Result<String> {
         Ok(format!("ff{}", hex::encode([color[2], color[1], color[0]])))
     }
+
+    /// Translucent, for filling in polygons.
+    pub(super) fn kml_fill_color(color: &[u8; 3]) -> Result<String> {
+        Ok(format!("80{}", hex::encode([color[2], color[1], color[0]])))
+    }
+
+    /// Escapes a path segment for an `<href>`, so that names with spaces and accents can be fetched.
+    pub(super) fn percent_encode(s: &str) -> Result<String> {
+        Ok(s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    char::from(b).to_string()
+                }
+                b => format!("%{:02X}", b),
+            })
+            .collect())
+    }
+
+    pub(super) fn meters(length: &Length) -> Result<String> {
+        Ok(format!("{:.2}", length.get::<meter>()))
+    }
+
+    pub(super) fn miles(length: &Length) -> Result<String> {
+        Ok(format!("{:.1}", length.get::<mile>()))
+    }
+
+    pub(super) fn square_miles(area: &Area) -> Result<String> {
+        Ok(format!("{:.0}", area.get::<square_mile>()))
+    }
 }
@@ -0,0 +1,230 @@
+use crate::geo::*;
+use crate::output::{quote, write_atomic, OutputWriter, Site};
+use crate::template::{Field, Territories as TerritoriesTemplate, TerritoriesGeoJson};
+use crate::Boundary;
+use anyhow::Result;
+use askama::Template;
+use rayon::prelude::*;
+use std::io::Write;
+use std::path::Path;
+use uom::si::area::{square_meter, square_mile};
+use uom::si::f64::Area;
+
+/// Grid cell size, in degrees.
+const RESOLUTION: f64 = 0.05;
+
+/// The part of the boundary closer to a team's line than to any other.
+#[derive(Debug, Clone)]
+pub struct Territory<'a> {
+    pub field: &'a Field,
+    /// Counter-clockwise outer rings of the polygons that make up the territory (which follow the
+    /// grid, so their edges are stairsteps).
+    pub polygons: Vec<Vec<Coordinate>>,
+    pub area: Area,
+}
+
+/// Assigns every `RESOLUTION`-degree grid cell inside the boundary to the field with the nearest
+/// line, returning a territory for each field (in the same order).
+pub fn territories<'a>(fields: &[&'a Field], boundary: &Boundary) -> Vec<Territory<'a>> {
+    let bounds = boundary.bounds();
+    let (west, south) = bounds.min().x_y();
+    let (east, north) = bounds.max().x_y();
+    let lines = fields
+        .iter()
+        .map(|field| field.line.line())
+        .collect::<Vec<_>>();
+
+    let columns = ((east - west) / RESOLUTION).ceil() as usize;
+    let rows = ((north - south) / RESOLUTION).ceil() as usize;
+    let runs = (0..rows)
+        .into_par_iter()
+        .map(|row| {
+            let lat = south + (row as f64 + 0.5) * RESOLUTION;
+            // Where the boundary crosses this row; cells between each pair are inside it.
+            let mut crossings = boundary
+                .0
+                .lines()
+                .filter(|line| (line.start.y <= lat) != (line.end.y <= lat))
+                .map(|line| {
+                    let t = (lat - line.start.y) / (line.end.y - line.start.y);
+                    line.start.x + (line.end.x - line.start.x) * t
+                })
+                .collect::<Vec<_>>();
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+            for column in 0..columns {
+                let center = Coordinate {
+                    x: west + (column as f64 + 0.5) * RESOLUTION,
+                    y: lat,
+                };
+                if crossings.iter().filter(|&&x| x < center.x).count() % 2 == 0 {
+                    continue;
+                }
+                let nearest = match nearest(&lines, center) {
+                    Some(nearest) => nearest,
+                    None => continue,
+                };
+                match runs.last_mut() {
+                    Some((i, _, end)) if *i == nearest && *end == column => *end += 1,
+                    _ => runs.push((nearest, column, column + 1)),
+                }
+            }
+            (lat, runs)
+        })
+        .collect::<Vec<_>>();
+
+    // Stack each run onto one it overlaps in the row below, to make polygons out of them.
+    let mut stacks = vec![Vec::<Stack>::new(); fields.len()];
+    let mut areas = vec![0.0; fields.len()];
+    let cell_height = RESOLUTION.to_radians() * EARTH_RADIUS;
+    for (row, (lat, runs)) in runs.into_iter().enumerate() {
+        let cell_width = RESOLUTION.to_radians() * lat.to_radians().cos() * EARTH_RADIUS;
+        let (south, north) = (lat - RESOLUTION / 2.0, lat + RESOLUTION / 2.0);
+        for (i, start, end) in runs {
+            areas[i] += cell_width * cell_height * (end - start) as f64;
+            let (start_x, end_x) = (
+                west + start as f64 * RESOLUTION,
+                west + end as f64 * RESOLUTION,
+            );
+            let stack = match stacks[i]
+                .iter_mut()
+                .find(|stack| stack.row + 1 == row && stack.start < end && start < stack.end)
+            {
+                Some(stack) => stack,
+                None => {
+                    stacks[i].push(Stack::default());
+                    stacks[i].last_mut().unwrap()
+                }
+            };
+            stack.row = row;
+            stack.start = start;
+            stack.end = end;
+            stack.left.push(Coordinate::from((start_x, south)));
+            stack.left.push(Coordinate::from((start_x, north)));
+            stack.right.push(Coordinate::from((end_x, south)));
+            stack.right.push(Coordinate::from((end_x, north)));
+        }
+    }
+
+    fields
+        .iter()
+        .zip(stacks)
+        .zip(areas)
+        .map(|((field, stacks), area)| Territory {
+            field,
+            polygons: stacks.into_iter().map(Stack::ring).collect(),
+            area: Area::new::<square_meter>(area),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+struct Stack {
+    /// The last row added, and the columns it covers.
+    row: usize,
+    start: usize,
+    end: usize,
+    left: Vec<Coordinate>,
+    right: Vec<Coordinate>,
+}
+
+impl Stack {
+    /// Up the east side and back down the west, so that the ring is counter-clockwise.
+    fn ring(self) -> Vec<Coordinate> {
+        let mut points = self.right;
+        points.extend(self.left.into_iter().rev());
+        points.dedup();
+        // Drop points in the middle of straight edges.
+        let n = points.len();
+        let mut ring = (0..n)
+            .filter(|&i| {
+                let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+                !(prev.x == point.x && point.x == next.x || prev.y == point.y && point.y == next.y)
+            })
+            .map(|i| points[i])
+            .collect::<Vec<_>>();
+        ring.push(ring[0]);
+        ring
+    }
+}
+
+/// The index of the line closest to `point`, measuring on a local equirectangular projection.
+fn nearest(lines: &[Line], point: Coordinate) -> Option<usize> {
+    let scale = point.y.to_radians().cos();
+    let project = |c: Coordinate| Coordinate {
+        x: (c.x - point.x) * scale,
+        y: c.y - point.y,
+    };
+    lines
+        .iter()
+        .map(|line| {
+            let (a, b) = (project(line.start), project(line.end));
+            let ab = b - a;
+            let t = -(a.x * ab.x + a.y * ab.y) / (ab.x * ab.x + ab.y * ab.y);
+            let closest = if t >= 1.0 {
+                b
+            } else if t > 0.0 {
+                a + ab * t
+            } else {
+                // Before the start, or a zero-length line (where `t` is NaN).
+                a
+            };
+            closest.x * closest.x + closest.y * closest.y
+        })
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(i, _)| i)
+}
+
+/// `territories.kml`, `territories.geojson` and `territories.csv`: the part of the map closest to
+/// each team's line, and its area.
+pub struct Territories;
+
+impl OutputWriter for Territories {
+    fn name(&self) -> &'static str {
+        "territories"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let territories = territories(site.fields, site.boundary);
+
+        let kml = TerritoriesTemplate {
+            revision: site.revision,
+            territories: &territories,
+        }
+        .render()?;
+        write_atomic(&dir.join("territories.kml"), |file| {
+            Ok(file.write_all(kml.as_bytes())?)
+        })?;
+
+        let geojson = TerritoriesGeoJson {
+            revision: site.revision,
+            territories: &territories,
+        }
+        .render()?;
+        write_atomic(&dir.join("territories.geojson"), |file| {
+            Ok(file.write_all(geojson.as_bytes())?)
+        })?;
+
+        let total = territories
+            .iter()
+            .map(|territory| territory.area.get::<square_mile>())
+            .sum::<f64>();
+        write_atomic(&dir.join("territories.csv"), |file| {
+            writeln!(file, "team,abbr,square_miles,percent")?;
+            for territory in &territories {
+                let area = territory.area.get::<square_mile>();
+                writeln!(
+                    file,
+                    "{},{},{:.0},{:.2}",
+                    quote(&territory.field.team.name),
+                    quote(&territory.field.team.abbr),
+                    area,
+                    area / total * 100.0,
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
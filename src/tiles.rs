@@ -0,0 +1,278 @@
+use crate::geo::*;
+use crate::output::{write_dir_atomic, OutputWriter, Site};
+use crate::template::Field;
+use crate::LatLonBox;
+use anyhow::Result;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use tiny_skia::{
+    Canvas, Color, FilterQuality, LineCap, LineJoin, Paint, PathBuilder, Pixmap, PixmapPaint,
+    Stroke, Transform,
+};
+
+const TILE_SIZE: f64 = 256.0;
+const LINE_WIDTH: f64 = 3.0;
+/// Labels are drawn while they're between these sizes (in pixels, along their long side).
+const LABEL_MIN_PIXELS: f64 = 12.0;
+const LABEL_MAX_PIXELS: f64 = 800.0;
+
+/// Web Mercator, in tile units at `zoom`.
+pub fn mercator(point: Coordinate, zoom: u8) -> Coordinate {
+    let n = f64::from(1u32 << zoom);
+    let lat = point.y.to_radians();
+    Coordinate {
+        x: (point.x + 180.0) / 360.0 * n,
+        y: (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0 * n,
+    }
+}
+
+/// The tile containing `point` (in tile units at `zoom`), clamped to the map.
+pub(crate) fn tile(point: Coordinate, zoom: u8) -> (u32, u32) {
+    let max = (1u32 << zoom) - 1;
+    (
+        (point.x.floor().max(0.0) as u32).min(max),
+        (point.y.floor().max(0.0) as u32).min(max),
+    )
+}
+
+/// Adds the tiles that `line` (in tile units at `zoom`) passes within `pad` tile units of.
+pub(crate) fn covering(line: &[Coordinate], zoom: u8, pad: f64, tiles: &mut BTreeSet<(u32, u32)>) {
+    for (a, b) in line.iter().zip(line.iter().skip(1)) {
+        let steps = ((b.x - a.x).abs().max((b.y - a.y).abs()) * 4.0)
+            .ceil()
+            .max(1.0);
+        for i in 0..=(steps as usize) {
+            let c = *a + (*b - *a) * (i as f64 / steps);
+            for &(dx, dy) in &[(-pad, -pad), (-pad, pad), (pad, -pad), (pad, pad)] {
+                tiles.insert(tile(c + Coordinate::from((dx, dy)), zoom));
+            }
+        }
+    }
+}
+
+/// The runs of `line` (in tile units) with segments that come within `buffer` tile units of tile
+/// (`x`, `y`), relative to the tile's origin. Dropping the far-off segments keeps coordinates
+/// small enough to render and encode accurately.
+pub(crate) fn clip(line: &[Coordinate], x: u32, y: u32, buffer: f64) -> Vec<Vec<Coordinate>> {
+    let origin = Coordinate::from((f64::from(x), f64::from(y)));
+    let (min, max) = (-buffer, 1.0 + buffer);
+    let mut runs: Vec<Vec<Coordinate>> = Vec::new();
+    let mut drawing = false;
+    for (a, b) in line.iter().zip(line.iter().skip(1)) {
+        let (a, b) = (*a - origin, *b - origin);
+        if a.x.min(b.x) > max || a.x.max(b.x) < min || a.y.min(b.y) > max || a.y.max(b.y) < min {
+            drawing = false;
+            continue;
+        }
+        if !drawing {
+            runs.push(vec![a]);
+            drawing = true;
+        }
+        runs.last_mut().unwrap().push(b);
+    }
+    runs
+}
+
+/// `tiles/{z}/{x}/{y}.png`: XYZ raster tiles of the lines and labels, for Leaflet, OpenLayers, or
+/// a Cesium imagery layer. Only tiles with something on them are written.
+pub struct Tiles {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+impl Default for Tiles {
+    fn default() -> Tiles {
+        Tiles {
+            min_zoom: 3,
+            max_zoom: 14,
+        }
+    }
+}
+
+impl OutputWriter for Tiles {
+    fn name(&self) -> &'static str {
+        "tiles"
+    }
+
+    fn write(&self, site: &Site<'_>, dir: &Path) -> Result<()> {
+        let labels = site
+            .fields
+            .iter()
+            .map(|field| {
+                let png = &site.images[&format!("{}.png", field.team.name)];
+                Ok(Pixmap::decode_png(png)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // tiny-skia's raster pipeline wants more stack than rayon's threads get by default.
+        let pool = ThreadPoolBuilder::new().stack_size(16 << 20).build()?;
+
+        write_dir_atomic(&dir.join("tiles"), |tmp_dir| {
+            for zoom in self.min_zoom..=self.max_zoom {
+                let zoomed = site
+                    .fields
+                    .iter()
+                    .zip(&labels)
+                    .map(|(field, label)| Zoomed::new(field, label, zoom))
+                    .collect::<Vec<_>>();
+
+                let mut tiles = BTreeMap::<(u32, u32), Vec<usize>>::new();
+                for (i, field) in zoomed.iter().enumerate() {
+                    for tile in field.tiles() {
+                        tiles.entry(tile).or_default().push(i);
+                    }
+                }
+
+                pool.install(|| {
+                    tiles
+                        .into_par_iter()
+                        .try_for_each(|((x, y), indices)| -> Result<()> {
+                            let pixmap = render(
+                                x,
+                                y,
+                                indices.iter().map(|&i| &zoomed[i]).collect::<Vec<_>>(),
+                            );
+                            let path = tmp_dir
+                                .join(zoom.to_string())
+                                .join(x.to_string())
+                                .join(format!("{}.png", y));
+                            fs::create_dir_all(path.parent().unwrap())?;
+                            fs::write(path, pixmap.encode_png()?)?;
+                            Ok(())
+                        })
+                })?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A field projected into tile units at a single zoom level.
+struct Zoomed<'a> {
+    field: &'a Field,
+    zoom: u8,
+    line: Vec<Coordinate>,
+    label: Option<(&'a Pixmap, LatLonBox)>,
+}
+
+impl<'a> Zoomed<'a> {
+    fn new(field: &'a Field, label: &'a Pixmap, zoom: u8) -> Zoomed<'a> {
+        let line = field.line.map(|c| mercator(c, zoom)).collect();
+        let height = (mercator(Coordinate::from((0.0, field.label.south)), zoom).y
+            - mercator(Coordinate::from((0.0, field.label.north)), zoom).y)
+            * TILE_SIZE;
+        Zoomed {
+            field,
+            zoom,
+            line,
+            label: if (LABEL_MIN_PIXELS..=LABEL_MAX_PIXELS).contains(&height) {
+                Some((label, field.label))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn tiles(&self) -> BTreeSet<(u32, u32)> {
+        let mut tiles = BTreeSet::new();
+        covering(&self.line, self.zoom, LINE_WIDTH / TILE_SIZE, &mut tiles);
+
+        if self.label.is_some() {
+            let region = &self.field.label_region;
+            let nw = mercator(Coordinate::from((region.west, region.north)), self.zoom);
+            let se = mercator(Coordinate::from((region.east, region.south)), self.zoom);
+            let (x0, y0) = tile(nw, self.zoom);
+            let (x1, y1) = tile(se, self.zoom);
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    tiles.insert((x, y));
+                }
+            }
+        }
+        tiles
+    }
+}
+
+fn render(x: u32, y: u32, fields: Vec<&Zoomed<'_>>) -> Pixmap {
+    let mut pixmap = Pixmap::new(TILE_SIZE as u32, TILE_SIZE as u32).unwrap();
+    let mut canvas = Canvas::from(pixmap.as_mut());
+    let origin = Coordinate::from((f64::from(x), f64::from(y)));
+    let to_pixels = |c: Coordinate| (c - origin) * TILE_SIZE;
+
+    let stroke = Stroke {
+        width: LINE_WIDTH as f32,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..Stroke::default()
+    };
+    for field in &fields {
+        let mut builder = PathBuilder::new();
+        for run in clip(&field.line, x, y, 1.0) {
+            let mut run = run.into_iter().map(|c| c * TILE_SIZE);
+            if let Some(start) = run.next() {
+                builder.move_to(start.x as f32, start.y as f32);
+            }
+            for c in run {
+                builder.line_to(c.x as f32, c.y as f32);
+            }
+        }
+        if let Some(path) = builder.finish() {
+            let color = field.field.team.color;
+            let mut paint = Paint::default();
+            paint.set_color(Color::from_rgba8(color[0], color[1], color[2], u8::MAX));
+            paint.anti_alias = true;
+            canvas.stroke_path(&path, &paint, &stroke);
+        }
+    }
+
+    for field in &fields {
+        let (label, bounds) = match field.label {
+            Some(label) => label,
+            None => continue,
+        };
+        let nw = to_pixels(mercator(
+            Coordinate::from((bounds.west, bounds.north)),
+            field.zoom,
+        ));
+        let se = to_pixels(mercator(
+            Coordinate::from((bounds.east, bounds.south)),
+            field.zoom,
+        ));
+        let center = (nw + se) / 2.0;
+        let scale_x = (se.x - nw.x) / f64::from(label.width());
+        let scale_y = (se.y - nw.y) / f64::from(label.height());
+        let (half_w, half_h) = (
+            f64::from(label.width()) / 2.0,
+            f64::from(label.height()) / 2.0,
+        );
+        // KML rotates counterclockwise; with y pointing down, that's a negative angle here.
+        let theta = -(180.0 - field.field.label_bearing).to_radians();
+        let (sin, cos) = theta.sin_cos();
+        let transform = Transform::from_row(
+            (cos * scale_x) as f32,
+            (sin * scale_x) as f32,
+            (-sin * scale_y) as f32,
+            (cos * scale_y) as f32,
+            (center.x - cos * scale_x * half_w + sin * scale_y * half_h) as f32,
+            (center.y - sin * scale_x * half_w - cos * scale_y * half_h) as f32,
+        );
+        if let Some(transform) = transform {
+            canvas.set_transform(transform);
+            canvas.draw_pixmap(
+                0,
+                0,
+                label.as_ref(),
+                &PixmapPaint {
+                    quality: FilterQuality::Bicubic,
+                    ..PixmapPaint::default()
+                },
+            );
+            canvas.reset_transform();
+        }
+    }
+
+    pixmap
+}
@@ -0,0 +1,35 @@
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref TIMINGS: Mutex<BTreeMap<&'static str, (Duration, usize)>> = Mutex::default();
+}
+
+/// Runs `f`, adding its wall time to the running total for `stage`. Stages run in parallel across
+/// teams, so the totals can add up to more than the time actually spent.
+pub fn time<T>(stage: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let mut timings = TIMINGS.lock().unwrap();
+    let entry = timings.entry(stage).or_default();
+    entry.0 += elapsed;
+    entry.1 += 1;
+    result
+}
+
+pub fn report(total: Duration) {
+    let timings = std::mem::take(&mut *TIMINGS.lock().unwrap());
+    for (stage, (elapsed, count)) in timings {
+        eprintln!(
+            "{:>8}: {:>9.3}s over {:>3} calls ({:.3}s avg)",
+            stage,
+            elapsed.as_secs_f64(),
+            count,
+            elapsed.as_secs_f64() / count as f64
+        );
+    }
+    eprintln!("{:>8}: {:>9.3}s wall", "total", total.as_secs_f64());
+}
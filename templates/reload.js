@@ -0,0 +1,16 @@
+(() => {
+  const events = new EventSource('/_navarro/events');
+  let overlay = null;
+
+  events.addEventListener('reload', () => window.location.reload());
+  events.addEventListener('build-error', (event) => {
+    if (overlay === null) {
+      overlay = document.createElement('pre');
+      overlay.style.cssText = 'position: fixed; z-index: 100; top: 0; left: 0; right: 0; bottom: 0;'
+        + 'margin: 0; padding: 1em; overflow: auto; white-space: pre-wrap;'
+        + 'background-color: rgba(0, 0, 0, 85%); color: #ff8080; font-size: 10pt;';
+      document.body.appendChild(overlay);
+    }
+    overlay.textContent = `navarro build failed:\n\n${event.data}`;
+  });
+})();
@@ -0,0 +1,135 @@
+//! Property tests for the line primitives in `geo.rs`, which have hand-tuned epsilons.
+
+use navarro::geo::*;
+use navarro::Survey;
+use proptest::prelude::*;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// Longitudes anywhere, but mostly within a degree of ±180°, where coordinates are largest (and
+/// rounding the worst). The primitives are planar, so segments there run past ±180° rather than
+/// wrapping around.
+fn longitude() -> impl Strategy<Value = f64> {
+    prop_oneof![-179.0..179.0, 179.0..179.999, -179.999..-179.0]
+}
+
+fn coordinate() -> impl Strategy<Value = Coordinate> {
+    (longitude(), -70.0..70.0).prop_map(|(x, y)| Coordinate { x, y })
+}
+
+/// Directions in radians, including ones within a hair of vertical.
+fn direction() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        -PI..PI,
+        (FRAC_PI_2 - 1e-6)..(FRAC_PI_2 + 1e-6),
+        (-FRAC_PI_2 - 1e-6)..(-FRAC_PI_2 + 1e-6),
+    ]
+}
+
+/// A segment with `point` at `t` of the way along it.
+fn through(point: Coordinate, angle: f64, length: f64, t: f64) -> Line {
+    let delta = Coordinate {
+        x: angle.cos() * length,
+        y: angle.sin() * length,
+    };
+    Line {
+        start: point - delta * t,
+        end: point + delta * (1.0 - t),
+    }
+}
+
+/// Distance (in degrees) from `point` to the infinite line through `line`.
+fn off_line(line: Line, point: Coordinate) -> f64 {
+    let (d, p) = (line.delta(), point - line.start);
+    (d.x * p.y - d.y * p.x).abs() / (d.x * d.x + d.y * d.y).sqrt()
+}
+
+proptest! {
+    #[test]
+    fn intersection_is_on_both_lines(
+        point in coordinate(),
+        (a, b) in (direction(), 0.02..PI - 0.02).prop_map(|(a, turn)| (a, a + turn)),
+        (length_a, length_b) in (0.01..20.0, 0.01..20.0),
+        (t_a, t_b) in (0.0..=1.0, 0.0..=1.0),
+    ) {
+        let (line_a, line_b) = (through(point, a, length_a, t_a), through(point, b, length_b, t_b));
+        let c = line_a.intersection(line_b).expect("lines aren't parallel");
+        prop_assert!(line_a.roughly_contains(c), "{:?} not on {:?}", c, line_a);
+        prop_assert!(line_b.roughly_contains(c), "{:?} not on {:?}", c, line_b);
+        prop_assert!(off_line(line_a, c) < 1e-6);
+        prop_assert!(off_line(line_b, c) < 1e-6);
+    }
+
+    /// Rounding can leave "parallel" lines a hair off, past the `1e-15` cutoff, in which case the
+    /// intersection is far away; either way, it's not on the segments.
+    #[test]
+    fn parallel_lines_dont_intersect(
+        point in coordinate(),
+        angle in direction(),
+        length in 0.01..20.0,
+        offset in 0.001..1.0,
+    ) {
+        let line = through(point, angle, length, 0.0);
+        let normal = Coordinate { x: -angle.sin(), y: angle.cos() } * offset;
+        let other = Line { start: line.start + normal, end: line.end + normal };
+        if let Some(c) = line.intersection(other) {
+            prop_assert!(!line.roughly_contains(c) || !other.roughly_contains(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn roughly_contains_the_bounding_box(
+        start in coordinate(),
+        angle in direction(),
+        length in 0.0..20.0,
+        t in 0.0..=1.0,
+        outside in 0.00001..1.0,
+    ) {
+        let line = through(start, angle, length, 0.0);
+        prop_assert!(line.roughly_contains(line.start));
+        prop_assert!(line.roughly_contains(line.end));
+        prop_assert!(line.roughly_contains(start + line.delta() * t));
+
+        let north = Coordinate { x: line.start.x, y: line.start.y.max(line.end.y) + outside };
+        prop_assert!(!line.roughly_contains(north));
+        let west = Coordinate { x: line.start.x.min(line.end.x) - outside, y: line.start.y };
+        prop_assert!(!line.roughly_contains(west));
+    }
+
+    #[test]
+    fn interpolate_covers_the_line(
+        start in coordinate(),
+        angle in direction(),
+        length in 0.001..60.0,
+    ) {
+        let line = through(start, angle, length, 0.0);
+        let points = line.interpolate().collect::<Vec<_>>();
+
+        prop_assert_eq!(points.first().copied(), Some(line.start));
+        // The last point is at or past the end of the line.
+        let last = *points.last().unwrap();
+        let along = (last - line.start).x * line.delta().x + (last - line.start).y * line.delta().y;
+        prop_assert!(along >= length * length * (1.0 - 1e-9));
+
+        for (a, b) in points.iter().zip(&points[1..]) {
+            let step = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+            prop_assert!(step <= 0.05 + 1e-9, "step of {} degrees", step);
+        }
+        for point in &points {
+            prop_assert!(off_line(line, *point) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn bearing_from_slope_round_trips(
+        field in coordinate(),
+        angle in direction(),
+    ) {
+        let slope = angle.tan();
+        let line = Survey::from_slope(field, slope).as_line();
+        let angle = slope.atan();
+        let round_trip = line.slope().atan();
+        // Slopes don't have a direction, so compare angles modulo a half turn.
+        let difference = (round_trip - angle + FRAC_PI_2).rem_euclid(PI) - FRAC_PI_2;
+        prop_assert!(difference.abs() < 1e-3, "{} became {}", slope, line.slope());
+    }
+}
@@ -0,0 +1,165 @@
+//! Checks every team's survey and clipped line against `tests/golden/surveys.csv`. After a change
+//! that's meant to move fields, run with `NAVARRO_BLESS=1` to rewrite the expected values.
+
+use navarro::geo::*;
+use navarro::{survey, Data, Team};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const TOLERANCE_METERS: f64 = 1.0;
+const TOLERANCE_DEGREES: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    field: Coordinate,
+    bearing: f64,
+    line: Option<Line>,
+}
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn compute() -> BTreeMap<String, Row> {
+    let boundary = Data::load(root()).unwrap().boundary;
+    fs::read_to_string(root().join("data").join("teams.csv"))
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.parse::<Team>().unwrap())
+        .filter_map(|team| {
+            let path = root().join("survey").join(&team.name).with_extension("kml");
+            let survey = survey::default(&fs::read_to_string(path).ok()?);
+            Some((
+                team.name,
+                Row {
+                    field: survey.field,
+                    bearing: survey.bearing,
+                    line: boundary.limit(&survey),
+                },
+            ))
+        })
+        .collect()
+}
+
+fn parse(csv: &str) -> BTreeMap<String, Row> {
+    csv.lines()
+        .skip(1)
+        .map(|line| {
+            let columns = line.split(',').collect::<Vec<_>>();
+            let number = |i: usize| columns[i].parse::<f64>().unwrap();
+            let line = if columns[4].is_empty() {
+                None
+            } else {
+                Some(Line {
+                    start: Coordinate::from((number(4), number(5))),
+                    end: Coordinate::from((number(6), number(7))),
+                })
+            };
+            let row = Row {
+                field: Coordinate::from((number(1), number(2))),
+                bearing: number(3),
+                line,
+            };
+            (columns[0].to_string(), row)
+        })
+        .collect()
+}
+
+fn format(rows: &BTreeMap<String, Row>) -> String {
+    let mut csv = String::from(
+        "team,longitude,latitude,bearing,west_longitude,west_latitude,east_longitude,east_latitude\n",
+    );
+    for (team, row) in rows {
+        write!(
+            csv,
+            "{},{:.7},{:.7},{:.7},",
+            team, row.field.x, row.field.y, row.bearing
+        )
+        .unwrap();
+        match row.line {
+            Some(line) => writeln!(
+                csv,
+                "{:.7},{:.7},{:.7},{:.7}",
+                line.start.x, line.start.y, line.end.x, line.end.y
+            ),
+            None => writeln!(csv, ",,,"),
+        }
+        .unwrap();
+    }
+    csv
+}
+
+fn meters(a: Coordinate, b: Coordinate) -> f64 {
+    Point::from(a).haversine_distance(&Point::from(b))
+}
+
+#[test]
+fn surveys() {
+    let path = root().join("tests").join("golden").join("surveys.csv");
+    let actual = compute();
+    if std::env::var_os("NAVARRO_BLESS").is_some() {
+        fs::write(&path, format(&actual)).unwrap();
+        return;
+    }
+    let expected = parse(&fs::read_to_string(&path).unwrap());
+
+    let teams = expected
+        .keys()
+        .chain(actual.keys())
+        .collect::<BTreeSet<_>>();
+    let mut failures = Vec::new();
+    for team in teams {
+        let (expected, actual) = match (expected.get(team), actual.get(team)) {
+            (Some(expected), Some(actual)) => (expected, actual),
+            (Some(_), None) => {
+                failures.push(format!("{}: no longer surveyed", team));
+                continue;
+            }
+            (None, _) => {
+                failures.push(format!("{}: not in surveys.csv", team));
+                continue;
+            }
+        };
+        let moved = meters(expected.field, actual.field);
+        if moved > TOLERANCE_METERS {
+            failures.push(format!("{}: field moved {:.2} m", team, moved));
+        }
+        let turned = (actual.bearing - expected.bearing + 180.0).rem_euclid(360.0) - 180.0;
+        if turned.abs() > TOLERANCE_DEGREES {
+            failures.push(format!("{}: field turned {:.4}°", team, turned));
+        }
+        match (expected.line, actual.line) {
+            (Some(expected), Some(actual)) => {
+                for &(end, a, b) in &[
+                    ("west", expected.start, actual.start),
+                    ("east", expected.end, actual.end),
+                ] {
+                    let moved = meters(a, b);
+                    if moved > TOLERANCE_METERS {
+                        failures.push(format!("{}: {} end moved {:.2} m", team, end, moved));
+                    }
+                }
+            }
+            (None, None) => {}
+            (_, actual) => failures.push(format!(
+                "{}: line {} the boundary",
+                team,
+                if actual.is_some() {
+                    "now reaches"
+                } else {
+                    "no longer reaches"
+                }
+            )),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} difference(s) from tests/golden/surveys.csv (rerun with NAVARRO_BLESS=1 if these are \
+         expected):\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,46 @@
+//! Renders the poster and checks that its fills made it into the PNG, which tiny-skia drops on
+//! images that are too large.
+
+use navarro::output::{self, Site};
+use navarro::Data;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tiny_skia::Pixmap;
+
+#[test]
+fn png_background() {
+    let data = Data::load(Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let site = Site {
+        revision: "test",
+        timestamp: 0,
+        boundary: &data.boundary,
+        places: &[],
+        states: &[],
+        story: &[],
+        teams: &[],
+        fields: &[],
+        images: &BTreeMap::new(),
+    };
+    let dir = std::env::temp_dir().join("navarro-poster-test");
+    fs::create_dir_all(&dir).unwrap();
+    output::by_name("poster")
+        .unwrap()
+        .write(&site, &dir)
+        .unwrap();
+
+    let png = Pixmap::load_png(dir.join("poster.png")).unwrap();
+    let corner = png.pixel(10, 10).unwrap();
+    assert_eq!(
+        (corner.red(), corner.green(), corner.blue(), corner.alpha()),
+        (255, 255, 255, 255),
+        "background isn't opaque white"
+    );
+    let middle = png.pixel(png.width() / 2, png.height() / 2).unwrap();
+    assert_eq!(
+        (middle.red(), middle.green(), middle.blue(), middle.alpha()),
+        (0xf2, 0xef, 0xe9, 255),
+        "the US isn't filled in"
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
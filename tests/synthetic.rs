@@ -0,0 +1,69 @@
+//! Runs each survey method on generated surveys, with and without noise.
+
+use navarro::geo::*;
+use navarro::survey::Method;
+use navarro::synthetic::accuracy;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use uom::si::f64::Length;
+use uom::si::length::meter;
+
+const FIELD: Coordinate = Coordinate {
+    x: -85.9512,
+    y: 31.8016,
+};
+
+#[test]
+fn exact_without_noise() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for &method in &Method::ALL {
+        for &bearing in &[0.5, 20.0, 75.0, 110.0, 160.0] {
+            let (distance, angle) = accuracy(
+                FIELD,
+                bearing,
+                method,
+                Length::new::<meter>(0.0),
+                1,
+                &mut rng,
+            )
+            .unwrap();
+            assert!(
+                distance.get::<meter>() < 0.01 && angle < 0.01,
+                "{} at {}°: off by {:.3} m and {:.4}°",
+                method,
+                bearing,
+                distance.get::<meter>(),
+                angle
+            );
+        }
+    }
+}
+
+/// Limits are about twice what each method does with half a meter of noise at a 20° bearing.
+#[test]
+fn accurate_with_noise() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for &(method, meters, degrees) in &[
+        (Method::HashMark, 0.5, 2.5),
+        (Method::SidelinesAnd50, 1.0, 0.6),
+        (Method::Stanford, 0.75, 1.0),
+        (Method::Syracuse, 1.0, 0.6),
+    ] {
+        let (distance, angle) = accuracy(
+            FIELD,
+            20.0,
+            method,
+            Length::new::<meter>(0.5),
+            200,
+            &mut rng,
+        )
+        .unwrap();
+        assert!(
+            distance.get::<meter>() < meters && angle < degrees,
+            "{}: off by {:.3} m and {:.4}°",
+            method,
+            distance.get::<meter>(),
+            angle
+        );
+    }
+}